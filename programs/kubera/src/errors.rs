@@ -64,4 +64,70 @@ pub enum LoanError {
 
     #[msg("Invalid PDA")]
     InvalidPDA,
+
+    #[msg("The token mint does not match the expected mint")]
+    InvalidMint,
+
+    #[msg("The loan offer has expired")]
+    LoanOfferExpired,
+
+    #[msg("The loan offer has not yet expired")]
+    LoanOfferNotExpired,
+
+    #[msg("The escrow vault of the loan offer was not provided")]
+    EscrowVaultNotProvided,
+
+    #[msg("The payment period must be positive and no longer than the loan term")]
+    InvalidPaymentPeriod,
+
+    #[msg("The grace period cannot be negative")]
+    InvalidGracePeriod,
+
+    #[msg("Invalid interest model parameters")]
+    InvalidInterestModel,
+
+    #[msg("The price feed does not match the loan or is not supported")]
+    InvalidPriceFeed,
+
+    #[msg("The oracle price must be positive")]
+    InvalidPrice,
+
+    #[msg("The oracle price is too old")]
+    StalePrice,
+
+    #[msg("The loan-to-value must be positive and below the liquidation threshold")]
+    InvalidLoanToValue,
+
+    #[msg("The loan is healthy and cannot be liquidated")]
+    LoanHealthy,
+
+    #[msg("Invalid auction parameters")]
+    InvalidAuctionParams,
+
+    #[msg("The collateral auction account was not provided")]
+    AuctionNotProvided,
+
+    #[msg("The auction price is above the bidder's limit")]
+    AuctionPriceAboveLimit,
+
+    #[msg("Only the protocol admin can do this")]
+    Unauthorized,
+
+    #[msg("The protocol is paused")]
+    ProtocolPaused,
+
+    #[msg("Invalid protocol settings")]
+    InvalidProtocolSettings,
+
+    #[msg("The mint is not allowed by the protocol")]
+    MintNotAllowed,
+
+    #[msg("The borrow cap of the mint is reached")]
+    BorrowCapExceeded,
+
+    #[msg("The offer exceeds the protocol risk limits")]
+    RiskLimitExceeded,
+
+    #[msg("The legacy account has an unknown layout")]
+    InvalidLegacyAccount,
 }
\ No newline at end of file
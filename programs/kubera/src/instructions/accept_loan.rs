@@ -1,16 +1,22 @@
 use anchor_lang::prelude::*;
-use anchor_spl::token::{self, Token, TokenAccount, Transfer};
-use crate::states::{LoanOffer, Loan, UserCollateral};
-use crate::models::LoanStatus;
+use anchor_spl::token::{self, Token, TokenAccount, Mint, Transfer, CloseAccount};
+use crate::states::{LoanOffer, Loan, UserCollateral, GuarantorOffer, MintConfig, ProtocolConfig};
+use crate::models::{LoanStatus, RepaymentSchedule, OriginationFeeCollected};
 use crate::errors::LoanError;
+use crate::math::{self, Rounding};
+use crate::oracle;
 
 #[derive(Accounts)]
-#[instruction(loan_offer_id: u64, use_guarantor: bool)]
+#[instruction(loan_offer_id: u64, use_guarantor: bool, guarantor_offer_id: u64)]
 pub struct AcceptLoan<'info> {
     #[account(mut)]
     pub borrower: Signer<'info>,
 
-    /// CHECK: This account is not a signer and should only be used as a reference
+    #[account(seeds = [b"protocol_config"], bump = protocol_config.bump)]
+    pub protocol_config: Account<'info, ProtocolConfig>,
+
+    /// CHECK: This account is not a signer, it only receives the rent of a closed offer vault
+    #[account(mut)]
     pub lender: AccountInfo<'info>,
 
     #[account(
@@ -30,22 +36,73 @@ pub struct AcceptLoan<'info> {
     )]
     pub loan: Account<'info, Loan>,
 
-    #[account(mut)]
+    #[account(
+        mut,
+        seeds = [b"user_collateral", borrower.key().as_ref(), loan_offer.collateral_mint.as_ref()],
+        bump
+    )]
     pub borrower_collateral: Account<'info, UserCollateral>,
 
-    #[account(mut)]
+    #[account(
+        mut,
+        constraint = borrower_token_account.mint == loan_offer.loan_mint @ LoanError::InvalidMint
+    )]
     pub borrower_token_account: Account<'info, TokenAccount>,
 
-    #[account(mut)]
+    #[account(
+        mut,
+        constraint = lender_token_account.mint == loan_offer.loan_mint @ LoanError::InvalidMint
+    )]
     pub lender_token_account: Account<'info, TokenAccount>,
 
+    // Receives the origination fee
     #[account(
         mut,
-        seeds = [b"collateral_token_account", borrower.key().as_ref()],
+        seeds = [b"treasury", loan_offer.loan_mint.as_ref()],
+        bump
+    )]
+    pub treasury_vault: Account<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        seeds = [b"collateral_token_account", borrower.key().as_ref(), loan_offer.collateral_mint.as_ref()],
         bump
     )]
     pub collateral_token_account: Account<'info, TokenAccount>,
 
+    #[account(address = loan_offer.loan_mint @ LoanError::InvalidMint)]
+    pub loan_mint: Account<'info, Mint>,
+
+    #[account(
+        address = loan_offer.collateral_mint @ LoanError::InvalidMint,
+        constraint = collateral_mint.key() == collateral_token_account.mint @ LoanError::InvalidMint
+    )]
+    pub collateral_mint: Account<'info, Mint>,
+
+    // Tracks the principal lent in the loan mint against its borrow cap
+    #[account(
+        mut,
+        seeds = [b"mint_config", loan_offer.loan_mint.as_ref()],
+        bump = loan_mint_config.bump
+    )]
+    pub loan_mint_config: Account<'info, MintConfig>,
+
+    #[account(
+        seeds = [b"mint_config", loan_offer.collateral_mint.as_ref()],
+        bump = collateral_mint_config.bump
+    )]
+    pub collateral_mint_config: Account<'info, MintConfig>,
+
+    #[account(
+        init,
+        payer = borrower,
+        token::mint = collateral_mint,
+        token::authority = loan,
+        seeds = [b"collateral_vault", loan.key().as_ref()],
+        bump
+    )]
+    pub collateral_vault: Account<'info, TokenAccount>,
+
     #[account(
         seeds = [b"loan_offer", lender.key().as_ref(), &loan_offer_id.to_le_bytes()],
         bump
@@ -53,17 +110,69 @@ pub struct AcceptLoan<'info> {
     /// CHECK: This is the PDA that will be used as the authority for the transfer
     pub loan_offer_pda: AccountInfo<'info>,
 
-    /// CHECK: This account is checked in the instruction handler
-    pub guarantor: Option<AccountInfo<'info>>,
+    #[account(
+        mut,
+        seeds = [b"offer_vault", loan_offer.key().as_ref()],
+        bump,
+        constraint = offer_vault.mint == loan_offer.loan_mint @ LoanError::InvalidMint
+    )]
+    pub offer_vault: Option<Account<'info, TokenAccount>>,
 
-    #[account(mut)]
+    #[account(
+        mut,
+        seeds = [
+            b"guarantor_offer",
+            guarantor_offer.guarantor.as_ref(),
+            &guarantor_offer_id.to_le_bytes()
+        ],
+        bump,
+        constraint = guarantor_offer.offer_id == guarantor_offer_id @ LoanError::InvalidGuarantorOffer,
+        constraint = guarantor_offer.collateral_mint == loan_offer.collateral_mint @ LoanError::InvalidMint
+    )]
+    pub guarantor_offer: Option<Account<'info, GuarantorOffer>>,
+
+    #[account(
+        mut,
+        seeds = [
+            b"user_collateral",
+            guarantor_offer.as_ref().map(|offer| offer.guarantor).unwrap_or_default().as_ref(),
+            loan_offer.collateral_mint.as_ref()
+        ],
+        bump
+    )]
     pub guarantor_collateral: Option<Account<'info, UserCollateral>>,
 
+    #[account(
+        mut,
+        seeds = [
+            b"collateral_token_account",
+            guarantor_offer.as_ref().map(|offer| offer.guarantor).unwrap_or_default().as_ref(),
+            loan_offer.collateral_mint.as_ref()
+        ],
+        bump,
+        constraint = guarantor_collateral_token_account.mint == loan_offer.collateral_mint @ LoanError::InvalidMint
+    )]
+    pub guarantor_collateral_token_account: Option<Account<'info, TokenAccount>>,
+
+    /// CHECK: Checked against the loan offer and parsed by the oracle module
+    pub loan_price_feed: Option<UncheckedAccount<'info>>,
+
+    /// CHECK: Checked against the loan offer and parsed by the oracle module
+    pub collateral_price_feed: Option<UncheckedAccount<'info>>,
+
     pub token_program: Program<'info, Token>,
     pub system_program: Program<'info, System>,
+    pub rent: Sysvar<'info, Rent>,
 }
 
-pub fn accept_loan(ctx: Context<AcceptLoan>, loan_offer_id: u64, use_guarantor: bool) -> Result<()> {
+pub fn accept_loan(ctx: Context<AcceptLoan>, loan_offer_id: u64, use_guarantor: bool, _guarantor_offer_id: u64) -> Result<()> {
+    require!(!ctx.accounts.protocol_config.pause.accept_loans, LoanError::ProtocolPaused);
+    // The mints may have been disabled since the offer was made
+    require!(
+        ctx.accounts.loan_mint_config.allow_loan && ctx.accounts.collateral_mint_config.allow_collateral,
+        LoanError::MintNotAllowed
+    );
+
     let loan_offer = &ctx.accounts.loan_offer;
     let borrower_collateral = &ctx.accounts.borrower_collateral;
     let guarantor_collateral = ctx.accounts.guarantor_collateral.as_ref();
@@ -93,24 +202,41 @@ pub fn accept_loan(ctx: Context<AcceptLoan>, loan_offer_id: u64, use_guarantor:
         LoanError::InvalidTokenAccountOwner
     );
 
-    // Calculate total collateral
-    let mut total_collateral = borrower_collateral.amount;
+    // Ensure the loan offer is still open
+    if let Some(offer_expires_at) = loan_offer.offer_expires_at {
+        require!(Clock::get()?.unix_timestamp < offer_expires_at, LoanError::LoanOfferExpired);
+    }
+
+    // Split the required collateral between the borrower and the guarantor,
+    // the borrower pledges first and the guarantor covers the shortfall
+    let required_collateral = required_collateral(&ctx)?;
+    let borrower_contribution = borrower_collateral.free.min(required_collateral);
     let mut guarantor_contribution = 0;
     if use_guarantor {
+        // The guarantor consents through a signed, unexpired guarantor offer
+        let guarantor_offer = ctx.accounts.guarantor_offer
+            .as_ref()
+            .ok_or(LoanError::GuarantorOfferNotFound)?;
+        require!(
+            guarantor_offer.expiry_date > Clock::get()?.unix_timestamp,
+            LoanError::GuarantorOfferExpired
+        );
+
         if let Some(guarantor_collateral) = guarantor_collateral {
-            let available_guarantor_collateral = guarantor_collateral.amount;
-            guarantor_contribution = loan_offer.required_collateral.saturating_sub(total_collateral);
-            guarantor_contribution = guarantor_contribution.min(available_guarantor_collateral);
-            total_collateral = total_collateral
-                .checked_add(guarantor_contribution)
-                .ok_or(LoanError::OverflowError)?;
+            guarantor_contribution = required_collateral
+                .saturating_sub(borrower_contribution)
+                .min(guarantor_collateral.free)
+                .min(guarantor_offer.amount);
         } else {
             return Err(LoanError::GuarantorNotProvided.into());
         }
     }
+    let total_collateral = borrower_contribution
+        .checked_add(guarantor_contribution)
+        .ok_or(LoanError::OverflowError)?;
 
     // Check if total collateral is sufficient
-    if total_collateral < loan_offer.required_collateral {
+    if total_collateral < required_collateral {
         return Err(LoanError::InsufficientCollateral.into());
     }
 
@@ -136,16 +262,57 @@ pub fn accept_loan(ctx: Context<AcceptLoan>, loan_offer_id: u64, use_guarantor:
     loan.borrower = ctx.accounts.borrower.key();
     loan.amount = loan_offer.amount;
     loan.interest_rate = loan_offer.interest_rate;
+    loan.interest_model = loan_offer.interest_model;
+    loan.loan_mint = loan_offer.loan_mint;
+    loan.collateral_mint = loan_offer.collateral_mint;
     loan.term = loan_offer.term;
     loan.start_time = Clock::get()?.unix_timestamp;
+    loan.principal_outstanding = loan_offer.amount;
+    loan.interest_outstanding = loan.initial_interest()?;
+    loan.last_accrual_time = loan.start_time;
+    loan.accrual_start = loan.start_time;
+    loan.interest_since_accrual_start = 0;
+    loan.schedule = loan_offer.schedule;
+    loan.payment_period = if loan_offer.schedule == RepaymentSchedule::Bullet {
+        loan_offer.term
+    } else {
+        loan_offer.payment_period
+    };
+    loan.installment_count = math::ceil_div(loan.term as u64, loan.payment_period as u64)?;
+    loan.next_due_date = loan.installment_due_date(1)?;
+    loan.grace_period = loan_offer.grace_period;
+    loan.penalty_rate_bps = loan_offer.penalty_rate_bps;
+    loan.loan_price_feed = loan_offer.loan_price_feed;
+    loan.collateral_price_feed = loan_offer.collateral_price_feed;
+    loan.ltv_bps = loan_offer.ltv_bps;
+    loan.liquidation_threshold_bps = loan_offer.liquidation_threshold_bps;
+    loan.liquidation_bonus_bps = loan_offer.liquidation_bonus_bps;
+    loan.auction = loan_offer.auction;
+    loan.last_penalty_accrual = loan.start_time;
     loan.status = LoanStatus::Active;
     loan.collateral = total_collateral;
+    loan.borrower_contribution = borrower_contribution;
+    loan.guarantor_contribution = guarantor_contribution;
     loan.loan_id = loan_offer_id;
+    loan.min_interest = loan_offer.min_interest;
+    loan.loan_offer = loan_offer.key();
+    loan.bump = ctx.bumps.loan;
 
     if use_guarantor {
-        loan.guarantor = ctx.accounts.guarantor.as_ref().map(|g| g.key());
+        if let Some(guarantor_offer) = ctx.accounts.guarantor_offer.as_ref() {
+            loan.guarantor = Some(guarantor_offer.guarantor);
+            loan.guarantor_interest_rate = guarantor_offer.interest_rate;
+        }
     }
 
+    // The origination fee is withheld from the principal, the borrower still owes the full amount
+    let origination_fee = math::apply_bps(
+        loan_offer.amount,
+        ctx.accounts.protocol_config.origination_fee_bps,
+        Rounding::Down,
+    )?;
+    let disbursed = loan_offer.amount - origination_fee;
+
     // Transfer funds from lender's token account to borrower's token account
     let loan_offer_seeds = &[
         b"loan_offer",
@@ -155,64 +322,176 @@ pub fn accept_loan(ctx: Context<AcceptLoan>, loan_offer_id: u64, use_guarantor:
     ];
     let signer = &[&loan_offer_seeds[..]];
 
-    let cpi_accounts = Transfer {
-        from: ctx.accounts.lender_token_account.to_account_info(),
-        to: ctx.accounts.borrower_token_account.to_account_info(),
-        authority: ctx.accounts.loan_offer_pda.to_account_info(),
-    };
-    let cpi_program = ctx.accounts.token_program.to_account_info();
-    let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
-    token::transfer(cpi_ctx, loan_offer.amount)?;
+    if loan_offer.use_escrow {
+        // Pay out from the offer vault, then close it and refund its rent to the lender
+        let offer_vault = ctx.accounts.offer_vault.as_ref().ok_or(LoanError::EscrowVaultNotProvided)?;
+
+        transfer_principal(&ctx, offer_vault.to_account_info(), disbursed, origination_fee, signer)?;
+
+        let remaining = offer_vault.amount.checked_sub(loan_offer.amount).ok_or(LoanError::InvalidAmount)?;
+        if remaining > 0 {
+            let cpi_accounts = Transfer {
+                from: offer_vault.to_account_info(),
+                to: ctx.accounts.lender_token_account.to_account_info(),
+                authority: ctx.accounts.loan_offer_pda.to_account_info(),
+            };
+            let cpi_program = ctx.accounts.token_program.to_account_info();
+            let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
+            token::transfer(cpi_ctx, remaining)?;
+        }
+
+        let cpi_accounts = CloseAccount {
+            account: offer_vault.to_account_info(),
+            destination: ctx.accounts.lender.to_account_info(),
+            authority: ctx.accounts.loan_offer_pda.to_account_info(),
+        };
+        let cpi_program = ctx.accounts.token_program.to_account_info();
+        let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
+        token::close_account(cpi_ctx)?;
+    } else {
+        transfer_principal(&ctx, ctx.accounts.lender_token_account.to_account_info(), disbursed, origination_fee, signer)?;
+    }
+
+    if origination_fee > 0 {
+        emit!(OriginationFeeCollected {
+            loan_id: loan_offer_id,
+            mint: loan_offer.loan_mint,
+            amount: origination_fee,
+        });
+    }
+
+    ctx.accounts.loan_mint_config.borrow(loan_offer.amount)?;
 
     // Update loan offer status
     let loan_offer = &mut ctx.accounts.loan_offer;
     loan_offer.status = LoanStatus::Active;
 
-    // If using guarantor, update guarantor collateral
-    // if use_guarantor {
-    //     if let Some(guarantor_collateral) = ctx.accounts.guarantor_collateral.as_mut() {
-    //         guarantor_collateral.amount = guarantor_collateral
-    //             .amount
-    //             .checked_sub(loan_offer.required_collateral)
-    //             .ok_or(LoanError::InsufficientCollateral)?;
-    //     }
-    // }
-
-    // New version
-    if use_guarantor {
-        if let Some(guarantor_collateral) = guarantor_collateral {
-            let available_guarantor_collateral = guarantor_collateral.amount;
-            guarantor_contribution = loan_offer.required_collateral.saturating_sub(total_collateral);
-            guarantor_contribution = guarantor_contribution.min(available_guarantor_collateral);
-            total_collateral = total_collateral
-                .checked_add(guarantor_contribution)
-                .ok_or(LoanError::OverflowError)?;
-        } else {
-            return Err(LoanError::GuarantorNotProvided.into());
-        }
+    // Lock the borrower's share of the collateral into the loan vault
+    let borrower_key = ctx.accounts.borrower.key();
+    let collateral_mint_key = ctx.accounts.collateral_mint.key();
+    let borrower_collateral_seeds = &[
+        b"user_collateral",
+        borrower_key.as_ref(),
+        collateral_mint_key.as_ref(),
+        &[ctx.bumps.borrower_collateral],
+    ];
+    let signer = &[&borrower_collateral_seeds[..]];
+
+    let cpi_accounts = Transfer {
+        from: ctx.accounts.collateral_token_account.to_account_info(),
+        to: ctx.accounts.collateral_vault.to_account_info(),
+        authority: ctx.accounts.borrower_collateral.to_account_info(),
+    };
+    let cpi_program = ctx.accounts.token_program.to_account_info();
+    let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
+    token::transfer(cpi_ctx, borrower_contribution)?;
+
+    // Lock the guarantor's share of the collateral into the loan vault
+    if guarantor_contribution > 0 {
+        let guarantor_collateral = ctx.accounts.guarantor_collateral
+            .as_ref()
+            .ok_or(LoanError::GuarantorNotProvided)?;
+        let guarantor_collateral_token_account = ctx.accounts.guarantor_collateral_token_account
+            .as_ref()
+            .ok_or(LoanError::GuarantorNotProvided)?;
+        let guarantor_key = guarantor_collateral.user;
+        let guarantor_bump = ctx.bumps.guarantor_collateral.ok_or(LoanError::GuarantorNotProvided)?;
+        let guarantor_collateral_seeds = &[
+            b"user_collateral",
+            guarantor_key.as_ref(),
+            collateral_mint_key.as_ref(),
+            &[guarantor_bump],
+        ];
+        let signer = &[&guarantor_collateral_seeds[..]];
+
+        let cpi_accounts = Transfer {
+            from: guarantor_collateral_token_account.to_account_info(),
+            to: ctx.accounts.collateral_vault.to_account_info(),
+            authority: guarantor_collateral.to_account_info(),
+        };
+        let cpi_program = ctx.accounts.token_program.to_account_info();
+        let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
+        token::transfer(cpi_ctx, guarantor_contribution)?;
     }
 
     // Update borrower collateral
-    let borrower_collateral = &mut ctx.accounts.borrower_collateral;
-    borrower_collateral.amount = borrower_collateral
-        .amount
-        .checked_sub(if use_guarantor { 
-            total_collateral.checked_sub(loan_offer.required_collateral).unwrap() 
-        } else { 
-            loan_offer.required_collateral 
-        })
-        .ok_or(LoanError::InsufficientCollateral)?;
-
-
-    // If using guarantor, update guarantor collateral
-    if use_guarantor && guarantor_contribution > 0 {
+    ctx.accounts.borrower_collateral.lock(borrower_contribution)?;
+
+
+    // Update guarantor collateral and consume the capacity of the guarantor offer
+    if guarantor_contribution > 0 {
         if let Some(guarantor_collateral) = ctx.accounts.guarantor_collateral.as_mut() {
-            guarantor_collateral.amount = guarantor_collateral
+            guarantor_collateral.lock(guarantor_contribution)?;
+        }
+        if let Some(guarantor_offer) = ctx.accounts.guarantor_offer.as_mut() {
+            guarantor_offer.amount = guarantor_offer
                 .amount
                 .checked_sub(guarantor_contribution)
-                .ok_or(LoanError::InsufficientCollateral)?;
+                .ok_or(LoanError::InvalidGuarantorOffer)?;
         }
     }
 
     Ok(())
-}
\ No newline at end of file
+}
+
+// Collateral the loan requires, sized from the oracle prices and the offer's loan-to-value when
+// the offer is priced, the lender's fixed amount otherwise
+fn required_collateral(ctx: &Context<AcceptLoan>) -> Result<u64> {
+    let loan_offer = &ctx.accounts.loan_offer;
+    let (Some(loan_feed_key), Some(collateral_feed_key)) =
+        (loan_offer.loan_price_feed, loan_offer.collateral_price_feed)
+    else {
+        return Ok(loan_offer.required_collateral);
+    };
+
+    let now = Clock::get()?.unix_timestamp;
+    let loan_price_feed = ctx.accounts.loan_price_feed.as_ref().ok_or(LoanError::InvalidPriceFeed)?;
+    let collateral_price_feed = ctx.accounts.collateral_price_feed.as_ref().ok_or(LoanError::InvalidPriceFeed)?;
+    let loan_price = oracle::load_price(loan_price_feed, loan_feed_key, now)?;
+    let collateral_price = oracle::load_price(collateral_price_feed, collateral_feed_key, now)?;
+
+    // The haircut lowers the value the collateral is credited with
+    let collateral_mint_config = &ctx.accounts.collateral_mint_config;
+    let ltv_bps = collateral_mint_config.haircut(loan_offer.ltv_bps)?;
+    require!(ltv_bps > 0, LoanError::InvalidLoanToValue);
+
+    oracle::collateral_for_loan(
+        loan_offer.amount,
+        &loan_price,
+        ctx.accounts.loan_mint_config.decimals,
+        &collateral_price,
+        collateral_mint_config.decimals,
+        ltv_bps,
+    )
+}
+
+// Pay the principal out of `from`, minus the origination fee which goes to the treasury
+fn transfer_principal<'info>(
+    ctx: &Context<AcceptLoan<'info>>,
+    from: AccountInfo<'info>,
+    disbursed: u64,
+    origination_fee: u64,
+    signer: &[&[&[u8]]],
+) -> Result<()> {
+    let cpi_accounts = Transfer {
+        from: from.clone(),
+        to: ctx.accounts.borrower_token_account.to_account_info(),
+        authority: ctx.accounts.loan_offer_pda.to_account_info(),
+    };
+    let cpi_program = ctx.accounts.token_program.to_account_info();
+    let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
+    token::transfer(cpi_ctx, disbursed)?;
+
+    if origination_fee > 0 {
+        let cpi_accounts = Transfer {
+            from,
+            to: ctx.accounts.treasury_vault.to_account_info(),
+            authority: ctx.accounts.loan_offer_pda.to_account_info(),
+        };
+        let cpi_program = ctx.accounts.token_program.to_account_info();
+        let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
+        token::transfer(cpi_ctx, origination_fee)?;
+    }
+
+    Ok(())
+}
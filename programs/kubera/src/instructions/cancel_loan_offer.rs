@@ -1,24 +1,110 @@
 use anchor_lang::prelude::*;
-use crate::states::LoanOffer;
-use crate::models::LoanStatus;
+use anchor_lang::solana_program::program_option::COption;
+use anchor_spl::token::{self, Token, TokenAccount, Revoke, Transfer, CloseAccount};
+use crate::states::{LoanOffer, ProtocolConfig};
+use crate::models::{LoanStatus, LoanOfferCancelled};
 use crate::errors::LoanError;
 
 #[derive(Accounts)]
 pub struct CancelLoanOffer<'info> {
     #[account(mut)]
     pub lender: Signer<'info>,
+
+    #[account(seeds = [b"protocol_config"], bump = protocol_config.bump)]
+    pub protocol_config: Account<'info, ProtocolConfig>,
     #[account(
         mut,
         seeds = [b"loan_offer", lender.key().as_ref(), &loan_offer.loan_offer_id.to_le_bytes()],
         bump,
         constraint = loan_offer.lender == lender.key(),
-        constraint = loan_offer.status == LoanStatus::Proposed @ LoanError::InvalidLoanStatus
+        constraint = loan_offer.status == LoanStatus::Proposed @ LoanError::InvalidLoanStatus,
+        close = lender
     )]
     pub loan_offer: Account<'info, LoanOffer>,
+    #[account(
+        mut,
+        constraint = lender_token_account.owner == lender.key() @ LoanError::InvalidTokenAccountOwner,
+        constraint = lender_token_account.mint == loan_offer.loan_mint @ LoanError::InvalidMint
+    )]
+    pub lender_token_account: Account<'info, TokenAccount>,
+    #[account(
+        mut,
+        seeds = [b"offer_vault", loan_offer.key().as_ref()],
+        bump,
+        constraint = offer_vault.mint == loan_offer.loan_mint @ LoanError::InvalidMint
+    )]
+    pub offer_vault: Option<Account<'info, TokenAccount>>,
+    pub token_program: Program<'info, Token>,
 }
 
+// Never paused, lenders can always get their funds back
 pub fn cancel_loan_offer(ctx: Context<CancelLoanOffer>) -> Result<()> {
+    if ctx.accounts.loan_offer.use_escrow {
+        let offer_vault = ctx.accounts.offer_vault.as_ref().ok_or(LoanError::EscrowVaultNotProvided)?;
+        refund_offer_vault(
+            &ctx.accounts.loan_offer,
+            ctx.bumps.loan_offer,
+            offer_vault,
+            &ctx.accounts.lender_token_account,
+            ctx.accounts.lender.to_account_info(),
+            &ctx.accounts.token_program,
+        )?;
+    } else {
+        // A token account has a single delegate, so the approval only belongs to this offer
+        // if it was the last one created from the account. Otherwise another live offer owns it.
+        let offer_key = ctx.accounts.loan_offer.key();
+        if ctx.accounts.lender_token_account.delegate == COption::Some(offer_key) {
+            let cpi_accounts = Revoke {
+                source: ctx.accounts.lender_token_account.to_account_info(),
+                authority: ctx.accounts.lender.to_account_info(),
+            };
+            let cpi_program = ctx.accounts.token_program.to_account_info();
+            let cpi_ctx = CpiContext::new(cpi_program, cpi_accounts);
+            token::revoke(cpi_ctx)?;
+        }
+    }
+
     let loan_offer = &mut ctx.accounts.loan_offer;
     loan_offer.status = LoanStatus::Cancelled;
+
+    emit!(LoanOfferCancelled {
+        lender: loan_offer.lender,
+        loan_offer_id: loan_offer.loan_offer_id,
+    });
+
     Ok(())
-}
\ No newline at end of file
+}
+
+// Send the escrowed funds of an offer back to the lender and close its vault
+pub(crate) fn refund_offer_vault<'info>(
+    loan_offer: &Account<'info, LoanOffer>,
+    loan_offer_bump: u8,
+    offer_vault: &Account<'info, TokenAccount>,
+    lender_token_account: &Account<'info, TokenAccount>,
+    lender: AccountInfo<'info>,
+    token_program: &Program<'info, Token>,
+) -> Result<()> {
+    let loan_offer_seeds = &[
+        b"loan_offer",
+        loan_offer.lender.as_ref(),
+        &loan_offer.loan_offer_id.to_le_bytes(),
+        &[loan_offer_bump],
+    ];
+    let signer = &[&loan_offer_seeds[..]];
+
+    let cpi_accounts = Transfer {
+        from: offer_vault.to_account_info(),
+        to: lender_token_account.to_account_info(),
+        authority: loan_offer.to_account_info(),
+    };
+    let cpi_ctx = CpiContext::new_with_signer(token_program.to_account_info(), cpi_accounts, signer);
+    token::transfer(cpi_ctx, offer_vault.amount)?;
+
+    let cpi_accounts = CloseAccount {
+        account: offer_vault.to_account_info(),
+        destination: lender,
+        authority: loan_offer.to_account_info(),
+    };
+    let cpi_ctx = CpiContext::new_with_signer(token_program.to_account_info(), cpi_accounts, signer);
+    token::close_account(cpi_ctx)
+}
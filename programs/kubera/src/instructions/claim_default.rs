@@ -0,0 +1,274 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token::{Token, TokenAccount};
+use crate::states::{CollateralAuction, Loan, UserCollateral, MintConfig, ProtocolConfig};
+use crate::models::{LoanStatus, LoanDefaulted, CollateralAuctionStarted};
+use crate::errors::LoanError;
+use crate::instructions::repay_loan::{transfer_from_vault, close_vault};
+use crate::math::{self, Rounding, BPS_DENOMINATOR};
+use crate::oracle;
+
+#[derive(Accounts)]
+pub struct ClaimDefault<'info> {
+    // Anyone can crank a default once the loan term is over
+    #[account(mut)]
+    pub caller: Signer<'info>,
+
+    #[account(seeds = [b"protocol_config"], bump = protocol_config.bump)]
+    pub protocol_config: Account<'info, ProtocolConfig>,
+
+    #[account(
+        mut,
+        constraint = loan.status == LoanStatus::Active @ LoanError::LoanNotActive,
+    )]
+    pub loan: Account<'info, Loan>,
+
+    #[account(
+        mut,
+        seeds = [b"user_collateral", loan.borrower.as_ref(), loan.collateral_mint.as_ref()],
+        bump
+    )]
+    pub borrower_collateral: Account<'info, UserCollateral>,
+
+    #[account(
+        mut,
+        constraint = guarantor_collateral.mint == loan.collateral_mint @ LoanError::InvalidMint
+    )]
+    pub guarantor_collateral: Option<Account<'info, UserCollateral>>,
+
+    #[account(
+        mut,
+        seeds = [b"collateral_token_account", loan.borrower.as_ref(), loan.collateral_mint.as_ref()],
+        bump,
+        constraint = collateral_token_account.mint == loan.collateral_mint @ LoanError::InvalidMint
+    )]
+    pub collateral_token_account: Account<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        constraint = guarantor_collateral_token_account.mint == loan.collateral_mint @ LoanError::InvalidMint
+    )]
+    pub guarantor_collateral_token_account: Option<Account<'info, TokenAccount>>,
+
+    /// CHECK: Receives the rent of the collateral vault, checked against the loan
+    #[account(mut, address = loan.borrower @ LoanError::UnauthorizedBorrower)]
+    pub borrower: AccountInfo<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"collateral_vault", loan.key().as_ref()],
+        bump,
+        constraint = collateral_vault.mint == loan.collateral_mint @ LoanError::InvalidMint
+    )]
+    pub collateral_vault: Account<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        constraint = lender_token_account.owner == loan.lender @ LoanError::InvalidTokenAccountOwner,
+        // Seized collateral is paid to the lender in the collateral token
+        constraint = lender_token_account.mint == loan.collateral_mint @ LoanError::InvalidMint
+    )]
+    pub lender_token_account: Account<'info, TokenAccount>,
+
+    // Principal leaving the loan no longer counts against the borrow cap of the mint
+    #[account(
+        mut,
+        seeds = [b"mint_config", loan.loan_mint.as_ref()],
+        bump = loan_mint_config.bump
+    )]
+    pub loan_mint_config: Account<'info, MintConfig>,
+
+    #[account(
+        seeds = [b"mint_config", loan.collateral_mint.as_ref()],
+        bump = collateral_mint_config.bump
+    )]
+    pub collateral_mint_config: Account<'info, MintConfig>,
+
+    /// CHECK: Checked against the loan and parsed by the oracle module
+    pub loan_price_feed: Option<UncheckedAccount<'info>>,
+
+    /// CHECK: Checked against the loan and parsed by the oracle module
+    pub collateral_price_feed: Option<UncheckedAccount<'info>>,
+
+    // Opened instead of seizing the collateral when the loan sells it by auction
+    #[account(
+        init,
+        payer = caller,
+        space = 8 + std::mem::size_of::<CollateralAuction>(),
+        seeds = [b"collateral_auction", loan.key().as_ref()],
+        bump
+    )]
+    pub collateral_auction: Option<Account<'info, CollateralAuction>>,
+
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+}
+
+pub fn claim_default(ctx: Context<ClaimDefault>) -> Result<()> {
+    require!(!ctx.accounts.protocol_config.pause.liquidations, LoanError::ProtocolPaused);
+
+    // Bring the accrued interest and late-payment penalty up to date first, an installment whose
+    // interest is still unpaid must not count as settled
+    let now = Clock::get()?.unix_timestamp;
+    let loan = &mut ctx.accounts.loan;
+    loan.accrue_interest(now)?;
+    loan.accrue_penalty(now)?;
+
+    // The loan can only be defaulted once the grace period after a missed due date has elapsed.
+    // Installments paid ahead count first, so the due date is the one actually missed.
+    loan.advance_installments(now)?;
+    require!(loan.count_missed_installments(now)? > 0, LoanError::LoanNotExpired);
+    require!(now >= loan.grace_end()?, LoanError::LoanNotExpired);
+
+    // Amount the borrower failed to repay (outstanding principal + interest + penalty)
+    let defaulted_amount = loan.amount_due()?;
+    ctx.accounts.loan_mint_config.release_borrowed(loan.principal_outstanding);
+
+    if loan.auction.is_some() {
+        return open_auction(ctx, defaulted_amount, now);
+    }
+
+    let loan = &ctx.accounts.loan;
+
+    // Waterfall: the borrower's collateral covers the debt first, the guarantor's only the shortfall
+    let defaulted_collateral = collateral_for_debt(&ctx, defaulted_amount, now)?;
+    let borrower_seized = loan.borrower_contribution.min(defaulted_collateral);
+    let guarantor_seized = loan.guarantor_contribution.min(defaulted_collateral - borrower_seized);
+    let borrower_released = ctx.accounts.collateral_vault.amount
+        .checked_sub(loan.guarantor_contribution)
+        .and_then(|v| v.checked_sub(borrower_seized))
+        .ok_or(LoanError::InsufficientCollateral)?;
+    let guarantor_released = loan.guarantor_contribution - guarantor_seized;
+
+    let seized = borrower_seized.checked_add(guarantor_seized).ok_or(LoanError::OverflowError)?;
+    transfer_from_vault(
+        loan,
+        &ctx.accounts.collateral_vault,
+        ctx.accounts.lender_token_account.to_account_info(),
+        &ctx.accounts.token_program,
+        seized,
+    )?;
+    transfer_from_vault(
+        loan,
+        &ctx.accounts.collateral_vault,
+        ctx.accounts.collateral_token_account.to_account_info(),
+        &ctx.accounts.token_program,
+        borrower_released,
+    )?;
+
+    if loan.guarantor_contribution > 0 {
+        let guarantor_collateral = ctx.accounts.guarantor_collateral
+            .as_ref()
+            .ok_or(LoanError::GuarantorNotProvided)?;
+        let guarantor_collateral_token_account = ctx.accounts.guarantor_collateral_token_account
+            .as_ref()
+            .ok_or(LoanError::GuarantorNotProvided)?;
+        require!(Some(guarantor_collateral.user) == loan.guarantor, LoanError::InvalidOwner);
+
+        // Verify that the guarantor's collateral token account is the PDA owned by the guarantor
+        let (guarantor_pool, _) = Pubkey::find_program_address(
+            &[
+                b"collateral_token_account",
+                guarantor_collateral.user.as_ref(),
+                guarantor_collateral.mint.as_ref()
+            ],
+            ctx.program_id
+        );
+        require!(guarantor_pool == guarantor_collateral_token_account.key(), LoanError::InvalidPDA);
+
+        transfer_from_vault(
+            loan,
+            &ctx.accounts.collateral_vault,
+            guarantor_collateral_token_account.to_account_info(),
+            &ctx.accounts.token_program,
+            guarantor_released,
+        )?;
+    }
+
+    // Close the empty vault and refund its rent to the borrower who paid for it
+    close_vault(
+        loan,
+        &ctx.accounts.collateral_vault,
+        ctx.accounts.borrower.to_account_info(),
+        &ctx.accounts.token_program,
+    )?;
+
+    // Unlock what was pledged, minus the seized part
+    ctx.accounts.borrower_collateral.release(loan.borrower_contribution, borrower_seized)?;
+    if loan.guarantor_contribution > 0 {
+        if let Some(guarantor_collateral) = ctx.accounts.guarantor_collateral.as_mut() {
+            guarantor_collateral.release(loan.guarantor_contribution, guarantor_seized)?;
+        }
+    }
+
+    let loan = &mut ctx.accounts.loan;
+    loan.status = LoanStatus::Defaulted;
+
+    emit!(LoanDefaulted {
+        loan_id: loan.loan_id,
+        defaulted_amount,
+        borrower_collateral_seized: borrower_seized,
+        guarantor_collateral_seized: guarantor_seized,
+    });
+
+    Ok(())
+}
+
+// Collateral worth `debt`, at the oracle prices when the loan is priced and at the ratio of
+// collateral to principal agreed at origination otherwise
+fn collateral_for_debt(ctx: &Context<ClaimDefault>, debt: u64, now: i64) -> Result<u64> {
+    let loan = &ctx.accounts.loan;
+    let (Some(loan_feed_key), Some(collateral_feed_key)) = (loan.loan_price_feed, loan.collateral_price_feed) else {
+        return loan.collateral_at_origination_ratio(debt);
+    };
+
+    let loan_price_feed = ctx.accounts.loan_price_feed.as_ref().ok_or(LoanError::InvalidPriceFeed)?;
+    let collateral_price_feed = ctx.accounts.collateral_price_feed.as_ref().ok_or(LoanError::InvalidPriceFeed)?;
+    let loan_price = oracle::load_price(loan_price_feed, loan_feed_key, now)?;
+    let collateral_price = oracle::load_price(collateral_price_feed, collateral_feed_key, now)?;
+
+    oracle::collateral_for_debt(
+        debt,
+        &loan_price,
+        ctx.accounts.loan_mint_config.decimals,
+        &collateral_price,
+        ctx.accounts.collateral_mint_config.decimals,
+    )
+}
+
+// Put the collateral up for sale. It stays in the vault and the pledges stay locked until a
+// bid settles the auction.
+fn open_auction(ctx: Context<ClaimDefault>, debt: u64, now: i64) -> Result<()> {
+    let loan = &mut ctx.accounts.loan;
+    let params = loan.auction.ok_or(LoanError::InvalidAuctionParams)?;
+    let auction = ctx.accounts.collateral_auction
+        .as_mut()
+        .ok_or(LoanError::AuctionNotProvided)?;
+
+    auction.loan = loan.key();
+    auction.opened_by = ctx.accounts.caller.key();
+    auction.debt = debt;
+    auction.collateral_amount = ctx.accounts.collateral_vault.amount;
+    auction.start_price = math::mul_div(
+        debt,
+        BPS_DENOMINATOR.checked_add(params.start_premium_bps).ok_or(LoanError::OverflowError)?,
+        BPS_DENOMINATOR,
+        Rounding::Up,
+    )?;
+    auction.floor_price = math::mul_div(debt, params.floor_bps, BPS_DENOMINATOR, Rounding::Down)?;
+    auction.curve = params.curve;
+    auction.start_time = now;
+    auction.bump = ctx.bumps.collateral_auction.ok_or(LoanError::AuctionNotProvided)?;
+
+    loan.status = LoanStatus::InAuction;
+
+    emit!(CollateralAuctionStarted {
+        loan_id: loan.loan_id,
+        debt,
+        collateral_amount: auction.collateral_amount,
+        start_price: auction.start_price,
+        floor_price: auction.floor_price,
+        start_time: now,
+    });
+
+    Ok(())
+}
@@ -0,0 +1,193 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token::{self, Token, TokenAccount, Transfer};
+use crate::states::{CollateralAuction, Loan, UserCollateral, ProtocolConfig};
+use crate::models::{LoanStatus, CollateralAuctionSettled};
+use crate::errors::LoanError;
+use crate::instructions::repay_loan::{transfer_from_vault, close_vault};
+use crate::math::{self, Rounding};
+
+// Buy the whole collateral of a defaulted loan at the current auction price, paid in the loan token
+#[derive(Accounts)]
+pub struct BidCollateralAuction<'info> {
+    pub bidder: Signer<'info>,
+
+    #[account(seeds = [b"protocol_config"], bump = protocol_config.bump)]
+    pub protocol_config: Account<'info, ProtocolConfig>,
+
+    #[account(
+        mut,
+        constraint = loan.status == LoanStatus::InAuction @ LoanError::LoanNotActive,
+    )]
+    pub loan: Account<'info, Loan>,
+
+    #[account(
+        mut,
+        seeds = [b"collateral_auction", loan.key().as_ref()],
+        bump = collateral_auction.bump,
+        has_one = loan,
+        close = auction_opener
+    )]
+    pub collateral_auction: Account<'info, CollateralAuction>,
+
+    /// CHECK: Gets back the rent of the auction, checked against the auction
+    #[account(mut, address = collateral_auction.opened_by)]
+    pub auction_opener: AccountInfo<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"user_collateral", loan.borrower.as_ref(), loan.collateral_mint.as_ref()],
+        bump
+    )]
+    pub borrower_collateral: Account<'info, UserCollateral>,
+
+    #[account(
+        mut,
+        constraint = guarantor_collateral.mint == loan.collateral_mint @ LoanError::InvalidMint,
+        constraint = Some(guarantor_collateral.user) == loan.guarantor @ LoanError::InvalidOwner
+    )]
+    pub guarantor_collateral: Option<Account<'info, UserCollateral>>,
+
+    /// CHECK: Receives the rent of the collateral vault, checked against the loan
+    #[account(mut, address = loan.borrower @ LoanError::UnauthorizedBorrower)]
+    pub borrower: AccountInfo<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"collateral_vault", loan.key().as_ref()],
+        bump,
+        constraint = collateral_vault.mint == loan.collateral_mint @ LoanError::InvalidMint
+    )]
+    pub collateral_vault: Account<'info, TokenAccount>,
+
+    // Pays the price in the loan token
+    #[account(
+        mut,
+        constraint = bidder_token_account.owner == bidder.key() @ LoanError::InvalidTokenAccountOwner,
+        constraint = bidder_token_account.mint == loan.loan_mint @ LoanError::InvalidMint
+    )]
+    pub bidder_token_account: Account<'info, TokenAccount>,
+
+    // Receives the collateral
+    #[account(
+        mut,
+        constraint = bidder_collateral_account.mint == loan.collateral_mint @ LoanError::InvalidMint
+    )]
+    pub bidder_collateral_account: Account<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        constraint = lender_token_account.owner == loan.lender @ LoanError::InvalidTokenAccountOwner,
+        constraint = lender_token_account.mint == loan.loan_mint @ LoanError::InvalidMint
+    )]
+    pub lender_token_account: Account<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        constraint = borrower_token_account.owner == loan.borrower @ LoanError::InvalidTokenAccountOwner,
+        constraint = borrower_token_account.mint == loan.loan_mint @ LoanError::InvalidMint
+    )]
+    pub borrower_token_account: Account<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        constraint = Some(guarantor_token_account.owner) == loan.guarantor @ LoanError::InvalidTokenAccountOwner,
+        constraint = guarantor_token_account.mint == loan.loan_mint @ LoanError::InvalidMint
+    )]
+    pub guarantor_token_account: Option<Account<'info, TokenAccount>>,
+
+    pub token_program: Program<'info, Token>,
+}
+
+pub fn bid_collateral_auction(ctx: Context<BidCollateralAuction>, max_price: u64) -> Result<()> {
+    require!(!ctx.accounts.protocol_config.pause.liquidations, LoanError::ProtocolPaused);
+
+    let now = Clock::get()?.unix_timestamp;
+    let auction = &ctx.accounts.collateral_auction;
+    let price = auction.current_price(now)?;
+    require!(price <= max_price, LoanError::AuctionPriceAboveLimit);
+
+    // Proceeds cover the penalty, interest and principal first
+    let debt = auction.debt;
+    let lender_proceeds = price.min(debt);
+    let surplus = price - lender_proceeds;
+    let shortfall = debt - lender_proceeds;
+
+    // The borrower's collateral answers for the debt first, so the borrower gets the surplus of
+    // its share of the sale and the guarantor the rest
+    let loan = &ctx.accounts.loan;
+    let pledged = loan.borrower_contribution
+        .checked_add(loan.guarantor_contribution)
+        .ok_or(LoanError::OverflowError)?;
+    let borrower_surplus = if pledged == 0 {
+        surplus
+    } else {
+        math::mul_div(price, loan.borrower_contribution, pledged, Rounding::Down)?
+            .saturating_sub(debt)
+            .min(surplus)
+    };
+    let guarantor_surplus = surplus - borrower_surplus;
+
+    pay(&ctx, ctx.accounts.lender_token_account.to_account_info(), lender_proceeds)?;
+    pay(&ctx, ctx.accounts.borrower_token_account.to_account_info(), borrower_surplus)?;
+    if guarantor_surplus > 0 {
+        let guarantor_token_account = ctx.accounts.guarantor_token_account
+            .as_ref()
+            .ok_or(LoanError::GuarantorNotProvided)?;
+        pay(&ctx, guarantor_token_account.to_account_info(), guarantor_surplus)?;
+    }
+
+    // Hand the whole vault to the bidder and close it
+    let loan = &ctx.accounts.loan;
+    let vault = &ctx.accounts.collateral_vault;
+    let token_program = &ctx.accounts.token_program;
+    transfer_from_vault(
+        loan,
+        vault,
+        ctx.accounts.bidder_collateral_account.to_account_info(),
+        token_program,
+        vault.amount,
+    )?;
+    close_vault(loan, vault, ctx.accounts.borrower.to_account_info(), token_program)?;
+
+    // The pledges were sold, nothing goes back to the collateral pools
+    let (borrower_contribution, guarantor_contribution) = (loan.borrower_contribution, loan.guarantor_contribution);
+    ctx.accounts.borrower_collateral.release(borrower_contribution, borrower_contribution)?;
+    if guarantor_contribution > 0 {
+        ctx.accounts.guarantor_collateral
+            .as_mut()
+            .ok_or(LoanError::GuarantorNotProvided)?
+            .release(guarantor_contribution, guarantor_contribution)?;
+    }
+
+    let loan = &mut ctx.accounts.loan;
+    loan.apply_repayment(lender_proceeds)?;
+    loan.status = LoanStatus::Defaulted;
+
+    emit!(CollateralAuctionSettled {
+        loan_id: loan.loan_id,
+        bidder: ctx.accounts.bidder.key(),
+        price,
+        lender_proceeds,
+        borrower_surplus,
+        guarantor_surplus,
+        shortfall,
+    });
+
+    Ok(())
+}
+
+// Move part of the price from the bidder to a recipient
+fn pay<'info>(ctx: &Context<BidCollateralAuction<'info>>, to: AccountInfo<'info>, amount: u64) -> Result<()> {
+    if amount == 0 {
+        return Ok(());
+    }
+
+    let cpi_accounts = Transfer {
+        from: ctx.accounts.bidder_token_account.to_account_info(),
+        to,
+        authority: ctx.accounts.bidder.to_account_info(),
+    };
+    let cpi_program = ctx.accounts.token_program.to_account_info();
+    let cpi_ctx = CpiContext::new(cpi_program, cpi_accounts);
+    token::transfer(cpi_ctx, amount)
+}
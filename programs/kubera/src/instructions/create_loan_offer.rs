@@ -1,15 +1,40 @@
 use anchor_lang::prelude::*;
-use anchor_spl::token::{self, Token, TokenAccount, Mint, Approve};
-use crate::states::{LoanOffer, LoanOfferCounter};
-use crate::models::{LoanStatus, LoanOfferCreated};
+use anchor_spl::token::{self, Token, TokenAccount, Mint, Approve, Transfer};
+use crate::states::{LoanOffer, LoanOfferCounter, MAX_INSTALLMENTS, MAX_PERIODS_PER_YEAR, MintConfig, ProtocolConfig};
+use crate::models::{AuctionParams, DecayCurve, InterestModel, LoanStatus, LoanOfferCreated, RepaymentSchedule};
 use crate::errors::LoanError;
+use crate::math::{self, Rounding, BPS_DENOMINATOR};
 
 #[derive(AnchorSerialize, AnchorDeserialize, Clone, Default)]
 pub struct CreateLoanOfferArgs {
     pub amount: u64,
     pub interest_rate: u64,
+    pub interest_model: InterestModel,
     pub term: i64,
     pub required_collateral: u64,
+    pub offer_expires_at: Option<i64>,
+    // Fund a per-offer vault instead of delegating the lender token account
+    pub use_escrow: bool,
+    // Minimum interest charged on early repayment
+    pub min_interest: Option<u64>,
+    pub schedule: RepaymentSchedule,
+    // Length of an installment period in seconds, ignored for bullet loans
+    pub payment_period: i64,
+    // Time after a missed due date before penalties accrue and default is possible
+    pub grace_period: i64,
+    // Annual penalty rate in basis points charged on the overdue balance
+    pub penalty_rate_bps: u64,
+    // Optional oracle pricing of both tokens in a common unit
+    pub loan_price_feed: Option<Pubkey>,
+    pub collateral_price_feed: Option<Pubkey>,
+    // Maximum loan value over collateral value at origination, in basis points
+    pub ltv_bps: u64,
+    // Loan-to-value above which the loan can be liquidated, in basis points
+    pub liquidation_threshold_bps: u64,
+    // Bonus paid to liquidators on top of the collateral matching the debt they repay
+    pub liquidation_bonus_bps: u64,
+    // Auction the collateral on default instead of handing it to the lender
+    pub auction: Option<AuctionParams>,
 }
 
 #[derive(Accounts)]
@@ -18,6 +43,9 @@ pub struct CreateLoanOffer<'info> {
     #[account(mut)]
     pub lender: Signer<'info>,
 
+    #[account(seeds = [b"protocol_config"], bump = protocol_config.bump)]
+    pub protocol_config: Account<'info, ProtocolConfig>,
+
     #[account(
         init,
         payer = lender,
@@ -34,6 +62,35 @@ pub struct CreateLoanOffer<'info> {
     #[account(mut)]
     pub lender_token_account: Account<'info, TokenAccount>,
 
+    #[account(
+        constraint = loan_mint.key() == lender_token_account.mint @ LoanError::InvalidMint
+    )]
+    pub loan_mint: Account<'info, Mint>,
+
+    pub collateral_mint: Account<'info, Mint>,
+
+    #[account(
+        seeds = [b"mint_config", loan_mint.key().as_ref()],
+        bump = loan_mint_config.bump
+    )]
+    pub loan_mint_config: Account<'info, MintConfig>,
+
+    #[account(
+        seeds = [b"mint_config", collateral_mint.key().as_ref()],
+        bump = collateral_mint_config.bump
+    )]
+    pub collateral_mint_config: Account<'info, MintConfig>,
+
+    #[account(
+        init,
+        payer = lender,
+        token::mint = loan_mint,
+        token::authority = loan_offer_account,
+        seeds = [b"offer_vault", loan_offer_account.key().as_ref()],
+        bump
+    )]
+    pub offer_vault: Option<Account<'info, TokenAccount>>,
+
     #[account(
         mut,
         seeds = [b"loan_offer_counter"],
@@ -57,11 +114,69 @@ pub struct CreateLoanOffer<'info> {
 }
 
 pub fn create_loan_offer(ctx: Context<CreateLoanOffer>, args: CreateLoanOfferArgs) -> Result<()> {
+    let config = &ctx.accounts.protocol_config;
+    require!(!config.pause.create_offers, LoanError::ProtocolPaused);
+    config.check_limits(args.amount, args.term, args.ltv_bps)?;
+    let loan_mint_config = &ctx.accounts.loan_mint_config;
+    let collateral_mint_config = &ctx.accounts.collateral_mint_config;
+    require!(
+        loan_mint_config.allow_loan && collateral_mint_config.allow_collateral,
+        LoanError::MintNotAllowed
+    );
+
     // Validation logic
     require!(args.amount > 0, LoanError::InvalidAmount);
     require!(args.interest_rate > 0, LoanError::InvalidInterestRate);
     require!(args.term > 0, LoanError::InvalidTerm);
-    require!(args.required_collateral > 0, LoanError::InvalidCollateralAmount);
+    if let InterestModel::Compound { periods_per_year } = args.interest_model {
+        require!(
+            periods_per_year > 0 && periods_per_year <= MAX_PERIODS_PER_YEAR,
+            LoanError::InvalidInterestModel
+        );
+    }
+    match (args.loan_price_feed, args.collateral_price_feed) {
+        (Some(loan_price_feed), Some(collateral_price_feed)) => require!(
+            // Priced offers can only use the feeds bound to the mints
+            loan_mint_config.price_feed == Some(loan_price_feed)
+                && collateral_mint_config.price_feed == Some(collateral_price_feed)
+                && args.ltv_bps > 0
+                && args.ltv_bps < args.liquidation_threshold_bps
+                && args.liquidation_threshold_bps <= BPS_DENOMINATOR
+                // A liquidation must leave the loan healthier than it found it
+                && math::mul_div(
+                    args.liquidation_threshold_bps,
+                    BPS_DENOMINATOR + args.liquidation_bonus_bps,
+                    BPS_DENOMINATOR,
+                    Rounding::Up,
+                )? < BPS_DENOMINATOR,
+            LoanError::InvalidLoanToValue
+        ),
+        (None, None) => require!(args.required_collateral > 0, LoanError::InvalidCollateralAmount),
+        _ => return Err(LoanError::InvalidPriceFeed.into()),
+    }
+    require!(args.grace_period >= 0, LoanError::InvalidGracePeriod);
+    if args.schedule != RepaymentSchedule::Bullet {
+        require!(
+            args.payment_period > 0 && args.payment_period <= args.term,
+            LoanError::InvalidPaymentPeriod
+        );
+        let installment_count = math::ceil_div(args.term as u64, args.payment_period as u64)?;
+        require!(installment_count <= MAX_INSTALLMENTS, LoanError::InvalidPaymentPeriod);
+    }
+    if let Some(auction) = args.auction {
+        let decay_period = match auction.curve {
+            DecayCurve::Linear { duration } => duration,
+            DecayCurve::Exponential { half_life } => half_life,
+        };
+        require!(
+            decay_period > 0
+                && auction.floor_bps <= BPS_DENOMINATOR.saturating_add(auction.start_premium_bps),
+            LoanError::InvalidAuctionParams
+        );
+    }
+    if let Some(offer_expires_at) = args.offer_expires_at {
+        require!(offer_expires_at > Clock::get()?.unix_timestamp, LoanError::InvalidExpiryDate);
+    }
 
     // Update counter
     let loan_offer_counter = &mut ctx.accounts.loan_offer_counter;
@@ -73,28 +188,60 @@ pub fn create_loan_offer(ctx: Context<CreateLoanOffer>, args: CreateLoanOfferArg
     loan_offer.lender = ctx.accounts.lender.key();
     loan_offer.amount = args.amount;
     loan_offer.interest_rate = args.interest_rate;
+    loan_offer.interest_model = args.interest_model;
     loan_offer.term = args.term;
     loan_offer.status = LoanStatus::Proposed;
+    loan_offer.loan_mint = ctx.accounts.loan_mint.key();
+    loan_offer.collateral_mint = ctx.accounts.collateral_mint.key();
     loan_offer.required_collateral = args.required_collateral;
     loan_offer.loan_offer_id = loan_offer_id;
+    loan_offer.offer_expires_at = args.offer_expires_at;
+    loan_offer.use_escrow = args.use_escrow;
+    loan_offer.min_interest = args.min_interest.unwrap_or(0);
+    loan_offer.schedule = args.schedule;
+    loan_offer.payment_period = args.payment_period;
+    loan_offer.grace_period = args.grace_period;
+    loan_offer.penalty_rate_bps = args.penalty_rate_bps;
+    loan_offer.loan_price_feed = args.loan_price_feed;
+    loan_offer.collateral_price_feed = args.collateral_price_feed;
+    loan_offer.ltv_bps = args.ltv_bps;
+    loan_offer.liquidation_threshold_bps = args.liquidation_threshold_bps;
+    loan_offer.liquidation_bonus_bps = args.liquidation_bonus_bps;
+    loan_offer.auction = args.auction;
 
-    // Delegate authority to the PDA
-    let cpi_accounts = Approve {
-        to: ctx.accounts.lender_token_account.to_account_info(),
-        delegate: ctx.accounts.loan_offer_pda.to_account_info(),
-        authority: ctx.accounts.lender.to_account_info(),
-    };
-    let cpi_program = ctx.accounts.token_program.to_account_info();
-    let cpi_ctx = CpiContext::new(cpi_program, cpi_accounts);
-    token::approve(cpi_ctx, args.amount)?;
+    if args.use_escrow {
+        // Fund the offer vault, each offer keeps its own funds
+        let offer_vault = ctx.accounts.offer_vault.as_ref().ok_or(LoanError::EscrowVaultNotProvided)?;
+        let cpi_accounts = Transfer {
+            from: ctx.accounts.lender_token_account.to_account_info(),
+            to: offer_vault.to_account_info(),
+            authority: ctx.accounts.lender.to_account_info(),
+        };
+        let cpi_program = ctx.accounts.token_program.to_account_info();
+        let cpi_ctx = CpiContext::new(cpi_program, cpi_accounts);
+        token::transfer(cpi_ctx, args.amount)?;
 
-    msg!("Delegated {} tokens to PDA: {:?}", args.amount, ctx.accounts.loan_offer_pda.key());
+        msg!("Escrowed {} tokens in vault: {:?}", args.amount, offer_vault.key());
+    } else {
+        // Delegate authority to the PDA
+        let cpi_accounts = Approve {
+            to: ctx.accounts.lender_token_account.to_account_info(),
+            delegate: ctx.accounts.loan_offer_pda.to_account_info(),
+            authority: ctx.accounts.lender.to_account_info(),
+        };
+        let cpi_program = ctx.accounts.token_program.to_account_info();
+        let cpi_ctx = CpiContext::new(cpi_program, cpi_accounts);
+        token::approve(cpi_ctx, args.amount)?;
+
+        msg!("Delegated {} tokens to PDA: {:?}", args.amount, ctx.accounts.loan_offer_pda.key());
+    }
 
     // Emit event
     emit!(LoanOfferCreated {
         lender: ctx.accounts.lender.key(),
         amount: args.amount,
         interest_rate: args.interest_rate,
+        interest_model: args.interest_model,
         term: args.term,
         required_collateral: args.required_collateral,
         loan_offer_id,
@@ -103,45 +250,24 @@ pub fn create_loan_offer(ctx: Context<CreateLoanOffer>, args: CreateLoanOfferArg
     Ok(())
 }
 
-#[derive(Accounts)]
-pub struct TransferToEscrow<'info> {
-    #[account(mut)]
-    pub lender: Signer<'info>,
-    #[account(mut)]
-    pub lender_token_account: Account<'info, TokenAccount>,
-    #[account(mut)]
-    pub escrow_token_account: Account<'info, TokenAccount>,
-    pub loan_token_mint: Account<'info, Mint>,
-    pub token_program: Program<'info, Token>,
-}
-
-pub fn transfer_to_escrow(ctx: Context<TransferToEscrow>, amount: u64) -> Result<()> {
-    let cpi_accounts = token::Transfer {
-        from: ctx.accounts.lender_token_account.to_account_info(),
-        to: ctx.accounts.escrow_token_account.to_account_info(),
-        authority: ctx.accounts.lender.to_account_info(),
-    };
-
-    let cpi_program = ctx.accounts.token_program.to_account_info();
-    let cpi_ctx = CpiContext::new(cpi_program, cpi_accounts);
-
-    token::transfer(cpi_ctx, amount)?;
-
-    Ok(())
-}
-
 #[derive(Accounts)]
 pub struct InitializeLoanOfferCounter<'info> {
     #[account(
         init,
-        payer = payer,
+        payer = admin,
         space = 8 + std::mem::size_of::<LoanOfferCounter>(),
         seeds = [b"loan_offer_counter"],
         bump
     )]
     pub loan_offer_counter: Account<'info, LoanOfferCounter>,
+    #[account(
+        seeds = [b"protocol_config"],
+        bump = protocol_config.bump,
+        has_one = admin @ LoanError::Unauthorized
+    )]
+    pub protocol_config: Account<'info, ProtocolConfig>,
     #[account(mut)]
-    pub payer: Signer<'info>,
+    pub admin: Signer<'info>,
     pub system_program: Program<'info, System>,
 }
 
@@ -1,6 +1,6 @@
 use anchor_lang::prelude::*;
 use anchor_spl::token::{self, Token, TokenAccount, Mint};
-use crate::states::UserCollateral;
+use crate::states::{UserCollateral, MintConfig, ProtocolConfig};
 use crate::errors::LoanError;
 
 #[derive(Accounts)]
@@ -8,46 +8,64 @@ pub struct DepositCollateral<'info> {
     #[account(mut)]
     pub user: Signer<'info>,
 
+    #[account(seeds = [b"protocol_config"], bump = protocol_config.bump)]
+    pub protocol_config: Account<'info, ProtocolConfig>,
+
     #[account(
         init_if_needed,
         payer = user,
         space = 8 + std::mem::size_of::<UserCollateral>(),
-        seeds = [b"user_collateral", user.key().as_ref()],
+        seeds = [b"user_collateral", user.key().as_ref(), mint.key().as_ref()],
         bump
     )]
     pub user_collateral: Account<'info, UserCollateral>,
 
-    #[account(mut)]
+    #[account(
+        mut,
+        constraint = user_token_account.mint == mint.key() @ LoanError::InvalidMint
+    )]
     pub user_token_account: Account<'info, TokenAccount>,
 
     #[account(
         init_if_needed,
         payer = user,
         token::mint = mint,
-        token::authority = user,
-        seeds = [b"collateral_token_account", user.key().as_ref()],
+        token::authority = user_collateral,
+        seeds = [b"collateral_token_account", user.key().as_ref(), mint.key().as_ref()],
         bump
     )]
     pub collateral_token_account: Account<'info, TokenAccount>,
 
     pub mint: Account<'info, Mint>,
 
+    #[account(
+        seeds = [b"mint_config", mint.key().as_ref()],
+        bump = mint_config.bump
+    )]
+    pub mint_config: Account<'info, MintConfig>,
+
     pub token_program: Program<'info, Token>,
     pub system_program: Program<'info, System>,
     pub rent: Sysvar<'info, Rent>,
 }
 
 pub fn deposit_collateral(ctx: Context<DepositCollateral>, amount: u64) -> Result<()> {
+    require!(!ctx.accounts.protocol_config.pause.deposits, LoanError::ProtocolPaused);
+    require!(ctx.accounts.mint_config.allow_collateral, LoanError::MintNotAllowed);
+
     let user_collateral = &mut ctx.accounts.user_collateral;
     
     // Initialize the user_collateral account if it's new
     if user_collateral.user == Pubkey::default() {
         user_collateral.user = ctx.accounts.user.key();
-        user_collateral.amount = 0;
+        user_collateral.mint = ctx.accounts.mint.key();
+        user_collateral.free = 0;
+        user_collateral.locked = 0;
+        user_collateral.active_loans = 0;
     }
     
     // Add the new amount to the existing collateral
-    user_collateral.amount = user_collateral.amount.checked_add(amount).ok_or(LoanError::OverflowError)?;
+    user_collateral.free = user_collateral.free.checked_add(amount).ok_or(LoanError::OverflowError)?;
 
     // Transfer tokens from user's token account to the collateral token account
     let cpi_accounts = token::Transfer {
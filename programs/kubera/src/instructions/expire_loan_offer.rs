@@ -0,0 +1,90 @@
+use anchor_lang::prelude::*;
+use anchor_lang::solana_program::program_option::COption;
+use anchor_spl::token::{self, Token, TokenAccount, Revoke};
+use crate::states::{LoanOffer, ProtocolConfig};
+use crate::models::{LoanStatus, LoanOfferExpired};
+use crate::errors::LoanError;
+use crate::instructions::cancel_loan_offer::refund_offer_vault;
+
+#[derive(Accounts)]
+pub struct ExpireLoanOffer<'info> {
+    // Anyone can crank an expired offer, the rent always goes back to the lender
+    pub caller: Signer<'info>,
+
+    #[account(seeds = [b"protocol_config"], bump = protocol_config.bump)]
+    pub protocol_config: Account<'info, ProtocolConfig>,
+
+    /// CHECK: Receives the rent of the closed loan offer, checked against the offer
+    #[account(mut, address = loan_offer.lender @ LoanError::UnauthorizedLender)]
+    pub lender: AccountInfo<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"loan_offer", loan_offer.lender.as_ref(), &loan_offer.loan_offer_id.to_le_bytes()],
+        bump,
+        constraint = loan_offer.status == LoanStatus::Proposed @ LoanError::InvalidLoanStatus,
+        close = lender
+    )]
+    pub loan_offer: Account<'info, LoanOffer>,
+
+    #[account(
+        mut,
+        constraint = lender_token_account.owner == loan_offer.lender @ LoanError::InvalidTokenAccountOwner,
+        constraint = lender_token_account.mint == loan_offer.loan_mint @ LoanError::InvalidMint
+    )]
+    pub lender_token_account: Account<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        seeds = [b"offer_vault", loan_offer.key().as_ref()],
+        bump,
+        constraint = offer_vault.mint == loan_offer.loan_mint @ LoanError::InvalidMint
+    )]
+    pub offer_vault: Option<Account<'info, TokenAccount>>,
+
+    pub token_program: Program<'info, Token>,
+}
+
+// Never paused, lenders can always get their funds back
+pub fn expire_loan_offer(ctx: Context<ExpireLoanOffer>) -> Result<()> {
+    let now = Clock::get()?.unix_timestamp;
+    let offer_expires_at = ctx.accounts.loan_offer.offer_expires_at.ok_or(LoanError::LoanOfferNotExpired)?;
+    require!(now >= offer_expires_at, LoanError::LoanOfferNotExpired);
+
+    let offer_key = ctx.accounts.loan_offer.key();
+    if ctx.accounts.loan_offer.use_escrow {
+        let offer_vault = ctx.accounts.offer_vault.as_ref().ok_or(LoanError::EscrowVaultNotProvided)?;
+        refund_offer_vault(
+            &ctx.accounts.loan_offer,
+            ctx.bumps.loan_offer,
+            offer_vault,
+            &ctx.accounts.lender_token_account,
+            ctx.accounts.lender.to_account_info(),
+            &ctx.accounts.token_program,
+        )?;
+    } else if ctx.accounts.caller.key() == ctx.accounts.loan_offer.lender
+        && ctx.accounts.lender_token_account.delegate == COption::Some(offer_key)
+    {
+        // The SPL token program only lets the owner revoke a delegate. When a third party cranks
+        // the expiry the approval is left in place, but the offer PDA can never sign again once
+        // the account is closed.
+        let cpi_accounts = Revoke {
+            source: ctx.accounts.lender_token_account.to_account_info(),
+            authority: ctx.accounts.caller.to_account_info(),
+        };
+        let cpi_program = ctx.accounts.token_program.to_account_info();
+        let cpi_ctx = CpiContext::new(cpi_program, cpi_accounts);
+        token::revoke(cpi_ctx)?;
+    }
+
+    let loan_offer = &mut ctx.accounts.loan_offer;
+    loan_offer.status = LoanStatus::Expired;
+
+    emit!(LoanOfferExpired {
+        lender: loan_offer.lender,
+        loan_offer_id: loan_offer.loan_offer_id,
+        expired_at: now,
+    });
+
+    Ok(())
+}
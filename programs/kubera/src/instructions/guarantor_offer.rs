@@ -1,5 +1,6 @@
 use anchor_lang::prelude::*;
-use crate::states::{GuarantorOffer, GuarantorOfferCounter};
+use anchor_spl::token::Mint;
+use crate::states::{GuarantorOffer, GuarantorOfferCounter, MintConfig, ProtocolConfig};
 use crate::errors::LoanError;
 
 #[derive(Accounts)]
@@ -8,6 +9,9 @@ pub struct CreateGuarantorOffer<'info> {
     #[account(mut)]
     pub guarantor: Signer<'info>,
 
+    #[account(seeds = [b"protocol_config"], bump = protocol_config.bump)]
+    pub protocol_config: Account<'info, ProtocolConfig>,
+
     #[account(
         init,
         payer = guarantor,
@@ -28,10 +32,21 @@ pub struct CreateGuarantorOffer<'info> {
     )]
     pub guarantor_offer_counter: Account<'info, GuarantorOfferCounter>,
 
+    // The only collateral the guarantor agrees to pledge under this offer
+    pub collateral_mint: Account<'info, Mint>,
+
+    #[account(
+        seeds = [b"mint_config", collateral_mint.key().as_ref()],
+        bump = mint_config.bump
+    )]
+    pub mint_config: Account<'info, MintConfig>,
+
     pub system_program: Program<'info, System>,
 }
 
 pub fn create_guarantor_offer(ctx: Context<CreateGuarantorOffer>, amount: u64, interest_rate: u64, expiry_date: i64) -> Result<()> {
+    require!(!ctx.accounts.protocol_config.pause.create_offers, LoanError::ProtocolPaused);
+    require!(ctx.accounts.mint_config.allow_collateral, LoanError::MintNotAllowed);
     require!(amount > 0, LoanError::InvalidAmount);
     require!(interest_rate > 0, LoanError::InvalidInterestRate);
     require!(expiry_date > Clock::get()?.unix_timestamp, LoanError::InvalidExpiryDate);
@@ -42,6 +57,7 @@ pub fn create_guarantor_offer(ctx: Context<CreateGuarantorOffer>, amount: u64, i
 
     let guarantor_offer = &mut ctx.accounts.guarantor_offer;
     guarantor_offer.guarantor = ctx.accounts.guarantor.key();
+    guarantor_offer.collateral_mint = ctx.accounts.collateral_mint.key();
     guarantor_offer.amount = amount;
     guarantor_offer.interest_rate = interest_rate;
     guarantor_offer.expiry_date = expiry_date;
@@ -54,14 +70,20 @@ pub fn create_guarantor_offer(ctx: Context<CreateGuarantorOffer>, amount: u64, i
 pub struct InitializeGuarantorOfferCounter<'info> {
     #[account(
         init,
-        payer = payer,
+        payer = admin,
         space = 8 + std::mem::size_of::<GuarantorOfferCounter>(),
         seeds = [b"guarantor_offer_counter"],
         bump
     )]
     pub guarantor_offer_counter: Account<'info, GuarantorOfferCounter>,
+    #[account(
+        seeds = [b"protocol_config"],
+        bump = protocol_config.bump,
+        has_one = admin @ LoanError::Unauthorized
+    )]
+    pub protocol_config: Account<'info, ProtocolConfig>,
     #[account(mut)]
-    pub payer: Signer<'info>,
+    pub admin: Signer<'info>,
     pub system_program: Program<'info, System>,
 }
 
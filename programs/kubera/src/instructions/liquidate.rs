@@ -0,0 +1,269 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token::{self, Token, TokenAccount, Transfer};
+use crate::states::{Loan, UserCollateral, MintConfig, ProtocolConfig};
+use crate::models::{LoanStatus, LoanLiquidated};
+use crate::errors::LoanError;
+use crate::instructions::repay_loan::{transfer_from_vault, close_vault};
+use crate::math::{self, Rounding, BPS_DENOMINATOR};
+use crate::oracle;
+
+#[derive(Accounts)]
+pub struct Liquidate<'info> {
+    // Any keeper can liquidate an unhealthy loan
+    pub liquidator: Signer<'info>,
+
+    #[account(seeds = [b"protocol_config"], bump = protocol_config.bump)]
+    pub protocol_config: Account<'info, ProtocolConfig>,
+
+    #[account(
+        mut,
+        constraint = loan.status == LoanStatus::Active @ LoanError::LoanNotActive,
+    )]
+    pub loan: Account<'info, Loan>,
+
+    #[account(
+        mut,
+        seeds = [b"user_collateral", loan.borrower.as_ref(), loan.collateral_mint.as_ref()],
+        bump
+    )]
+    pub borrower_collateral: Account<'info, UserCollateral>,
+
+    #[account(
+        mut,
+        constraint = guarantor_collateral.mint == loan.collateral_mint @ LoanError::InvalidMint,
+        constraint = Some(guarantor_collateral.user) == loan.guarantor @ LoanError::InvalidOwner
+    )]
+    pub guarantor_collateral: Option<Account<'info, UserCollateral>>,
+
+    #[account(
+        mut,
+        seeds = [b"collateral_token_account", loan.borrower.as_ref(), loan.collateral_mint.as_ref()],
+        bump
+    )]
+    pub collateral_token_account: Account<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        seeds = [
+            b"collateral_token_account",
+            loan.guarantor.unwrap_or_default().as_ref(),
+            loan.collateral_mint.as_ref()
+        ],
+        bump
+    )]
+    pub guarantor_collateral_token_account: Option<Account<'info, TokenAccount>>,
+
+    /// CHECK: Receives the rent of the collateral vault, checked against the loan
+    #[account(mut, address = loan.borrower @ LoanError::UnauthorizedBorrower)]
+    pub borrower: AccountInfo<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"collateral_vault", loan.key().as_ref()],
+        bump,
+        constraint = collateral_vault.mint == loan.collateral_mint @ LoanError::InvalidMint
+    )]
+    pub collateral_vault: Account<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        seeds = [b"mint_config", loan.loan_mint.as_ref()],
+        bump = loan_mint_config.bump
+    )]
+    pub loan_mint_config: Account<'info, MintConfig>,
+
+    #[account(
+        seeds = [b"mint_config", loan.collateral_mint.as_ref()],
+        bump = collateral_mint_config.bump
+    )]
+    pub collateral_mint_config: Account<'info, MintConfig>,
+
+    // Pays the debt on behalf of the borrower
+    #[account(
+        mut,
+        constraint = liquidator_token_account.owner == liquidator.key() @ LoanError::InvalidTokenAccountOwner,
+        constraint = liquidator_token_account.mint == loan.loan_mint @ LoanError::InvalidMint
+    )]
+    pub liquidator_token_account: Account<'info, TokenAccount>,
+
+    // Receives the seized collateral and the bonus
+    #[account(
+        mut,
+        constraint = liquidator_collateral_account.mint == loan.collateral_mint @ LoanError::InvalidMint
+    )]
+    pub liquidator_collateral_account: Account<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        constraint = lender_token_account.owner == loan.lender @ LoanError::InvalidTokenAccountOwner,
+        constraint = lender_token_account.mint == loan.loan_mint @ LoanError::InvalidMint
+    )]
+    pub lender_token_account: Account<'info, TokenAccount>,
+
+    /// CHECK: Checked against the loan and parsed by the oracle module
+    pub loan_price_feed: UncheckedAccount<'info>,
+
+    /// CHECK: Checked against the loan and parsed by the oracle module
+    pub collateral_price_feed: UncheckedAccount<'info>,
+
+    pub token_program: Program<'info, Token>,
+}
+
+pub fn liquidate(ctx: Context<Liquidate>, repay_amount: u64) -> Result<()> {
+    require!(!ctx.accounts.protocol_config.pause.liquidations, LoanError::ProtocolPaused);
+
+    let now = Clock::get()?.unix_timestamp;
+    let loan = &mut ctx.accounts.loan;
+    let loan_feed_key = loan.loan_price_feed.ok_or(LoanError::InvalidPriceFeed)?;
+    let collateral_feed_key = loan.collateral_price_feed.ok_or(LoanError::InvalidPriceFeed)?;
+    let loan_price = oracle::load_price(&ctx.accounts.loan_price_feed, loan_feed_key, now)?;
+    let collateral_price = oracle::load_price(&ctx.accounts.collateral_price_feed, collateral_feed_key, now)?;
+    let loan_decimals = ctx.accounts.loan_mint_config.decimals;
+    let collateral_decimals = ctx.accounts.collateral_mint_config.decimals;
+
+    // Bring the debt up to date before measuring the health of the loan
+    loan.accrue_interest(now)?;
+    loan.accrue_penalty(now)?;
+    let debt = loan.amount_due()?;
+    let collateral = loan.borrower_contribution
+        .checked_add(loan.guarantor_contribution)
+        .ok_or(LoanError::OverflowError)?;
+
+    // Health is measured on the collateral after the haircut, seizures use its full value
+    let ltv_bps = oracle::loan_to_value_bps(
+        debt,
+        &loan_price,
+        loan_decimals,
+        ctx.accounts.collateral_mint_config.haircut(collateral)?,
+        &collateral_price,
+        collateral_decimals,
+    )?;
+    require!(ltv_bps > loan.liquidation_threshold_bps, LoanError::LoanHealthy);
+    let health_factor_bps = oracle::health_factor_bps(ltv_bps, loan.liquidation_threshold_bps)?;
+
+    // The liquidator repays part or all of the debt and receives collateral of the same value
+    // plus the bonus, capped by what is left in the vault
+    let repaid = repay_amount.min(debt);
+    require!(repaid > 0, LoanError::InvalidAmount);
+    let repaid_value = oracle::value_of(repaid, &loan_price, loan_decimals, Rounding::Down)?;
+    let seized_value = math::mul_div_u128(
+        repaid_value,
+        (BPS_DENOMINATOR + loan.liquidation_bonus_bps) as u128,
+        BPS_DENOMINATOR as u128,
+        Rounding::Down,
+    )?;
+    let collateral_seized = oracle::amount_for_value(seized_value, &collateral_price, collateral_decimals, Rounding::Down)?
+        .min(collateral);
+
+    let (_, _, principal_repaid) = loan.apply_repayment(repaid)?;
+    // Installments covered by the repayment no longer count as missed
+    loan.advance_installments(now)?;
+    ctx.accounts.loan_mint_config.release_borrowed(principal_repaid);
+    let remaining_debt = loan.amount_due()?;
+
+    // Waterfall: the borrower's collateral is seized first, the guarantor's only for the rest
+    let borrower_seized = collateral_seized.min(loan.borrower_contribution);
+    let guarantor_seized = collateral_seized - borrower_seized;
+    loan.borrower_contribution -= borrower_seized;
+    loan.guarantor_contribution -= guarantor_seized;
+    loan.collateral = loan.collateral.saturating_sub(collateral_seized);
+
+    let cpi_accounts = Transfer {
+        from: ctx.accounts.liquidator_token_account.to_account_info(),
+        to: ctx.accounts.lender_token_account.to_account_info(),
+        authority: ctx.accounts.liquidator.to_account_info(),
+    };
+    let cpi_program = ctx.accounts.token_program.to_account_info();
+    let cpi_ctx = CpiContext::new(cpi_program, cpi_accounts);
+    token::transfer(cpi_ctx, repaid)?;
+
+    transfer_from_vault(
+        &ctx.accounts.loan,
+        &ctx.accounts.collateral_vault,
+        ctx.accounts.liquidator_collateral_account.to_account_info(),
+        &ctx.accounts.token_program,
+        collateral_seized,
+    )?;
+
+    ctx.accounts.borrower_collateral.seize(borrower_seized)?;
+    if guarantor_seized > 0 {
+        let guarantor_remaining = ctx.accounts.loan.guarantor_contribution;
+        let guarantor_collateral = ctx.accounts.guarantor_collateral
+            .as_mut()
+            .ok_or(LoanError::GuarantorNotProvided)?;
+        guarantor_collateral.seize(guarantor_seized)?;
+        // A guarantor whose whole pledge was seized no longer backs the loan
+        if guarantor_remaining == 0 {
+            guarantor_collateral.release(0, 0)?;
+        }
+    }
+
+    // The loan is closed once the debt is cleared or no collateral is left, any debt left over
+    // at that point is a loss for the lender
+    let loan = &ctx.accounts.loan;
+    let closed = remaining_debt == 0 || loan.borrower_contribution + loan.guarantor_contribution == 0;
+    if closed {
+        close_loan(&ctx)?;
+
+        let loan = &ctx.accounts.loan;
+        let (borrower_remaining, guarantor_remaining) = (loan.borrower_contribution, loan.guarantor_contribution);
+        ctx.accounts.borrower_collateral.release(borrower_remaining, 0)?;
+        if guarantor_remaining > 0 {
+            ctx.accounts.guarantor_collateral
+                .as_mut()
+                .ok_or(LoanError::GuarantorNotProvided)?
+                .release(guarantor_remaining, 0)?;
+        }
+        // Principal left unpaid is written off
+        let principal_outstanding = ctx.accounts.loan.principal_outstanding;
+        ctx.accounts.loan_mint_config.release_borrowed(principal_outstanding);
+        ctx.accounts.loan.status = LoanStatus::Liquidated;
+    }
+
+    let loan = &ctx.accounts.loan;
+    emit!(LoanLiquidated {
+        loan_id: loan.loan_id,
+        liquidator: ctx.accounts.liquidator.key(),
+        debt_repaid: repaid,
+        borrower_collateral_seized: borrower_seized,
+        guarantor_collateral_seized: guarantor_seized,
+        health_factor_bps,
+        remaining_debt,
+        closed,
+    });
+
+    Ok(())
+}
+
+// Return the collateral left in the vault to the collateral accounts, close the vault and
+// unlock the remaining pledges
+fn close_loan(ctx: &Context<Liquidate>) -> Result<()> {
+    let loan = &ctx.accounts.loan;
+
+    transfer_from_vault(
+        loan,
+        &ctx.accounts.collateral_vault,
+        ctx.accounts.collateral_token_account.to_account_info(),
+        &ctx.accounts.token_program,
+        loan.borrower_contribution,
+    )?;
+    if loan.guarantor_contribution > 0 {
+        let guarantor_collateral_token_account = ctx.accounts.guarantor_collateral_token_account
+            .as_ref()
+            .ok_or(LoanError::GuarantorNotProvided)?;
+        transfer_from_vault(
+            loan,
+            &ctx.accounts.collateral_vault,
+            guarantor_collateral_token_account.to_account_info(),
+            &ctx.accounts.token_program,
+            loan.guarantor_contribution,
+        )?;
+    }
+
+    close_vault(
+        loan,
+        &ctx.accounts.collateral_vault,
+        ctx.accounts.borrower.to_account_info(),
+        &ctx.accounts.token_program,
+    )
+}
@@ -0,0 +1,275 @@
+use anchor_lang::prelude::*;
+use anchor_lang::system_program;
+use anchor_lang::Discriminator;
+use anchor_spl::token::{self, Token, TokenAccount, Mint, Transfer, CloseAccount};
+use crate::states::{UserCollateral, ProtocolConfig};
+use crate::errors::LoanError;
+
+// Ledger layout used before collateral accounts were keyed by mint
+#[derive(AnchorDeserialize)]
+struct LegacyUserCollateral {
+    user: Pubkey,
+    free: u64,
+    locked: u64,
+    active_loans: u64,
+}
+
+// Ledger layout of the first version, a single balance. Accepting a loan subtracted the pledge
+// from it but left the tokens in the pool, and nothing ever gave them back.
+#[derive(AnchorDeserialize)]
+struct BaselineUserCollateral {
+    user: Pubkey,
+    amount: u64,
+}
+
+const LEGACY_LEDGER_LEN: usize = 32 + 8 + 8 + 8;
+const BASELINE_LEDGER_LEN: usize = 32 + 8;
+
+impl LegacyUserCollateral {
+    // Both layouts share the discriminator, the size of the account tells them apart.
+    // `pool_amount` is the balance of the legacy pool moved by the migration.
+    fn decode(data: &[u8], pool_amount: u64) -> Result<Self> {
+        require!(
+            data.len() >= 8 && data[..8] == UserCollateral::DISCRIMINATOR,
+            LoanError::InvalidOwner
+        );
+        let mut payload = &data[8..];
+        match payload.len() {
+            LEGACY_LEDGER_LEN => Ok(Self::deserialize(&mut payload)?),
+            BASELINE_LEDGER_LEN => {
+                // The first version never moved anything out of the pool, so the whole pool is
+                // free, including the pledges its balance lost track of
+                let baseline = BaselineUserCollateral::deserialize(&mut payload)?;
+                require!(pool_amount >= baseline.amount, LoanError::InvalidLegacyAccount);
+                Ok(Self {
+                    user: baseline.user,
+                    free: pool_amount,
+                    locked: 0,
+                    active_loans: 0,
+                })
+            }
+            _ => Err(LoanError::InvalidLegacyAccount.into()),
+        }
+    }
+
+    // Add the legacy balances to the per-mint ledger, which may already hold deposits
+    fn add_to(&self, ledger: &mut UserCollateral) -> Result<()> {
+        ledger.free = ledger.free.checked_add(self.free).ok_or(LoanError::OverflowError)?;
+        ledger.locked = ledger.locked.checked_add(self.locked).ok_or(LoanError::OverflowError)?;
+        ledger.active_loans = ledger.active_loans
+            .checked_add(self.active_loans)
+            .ok_or(LoanError::OverflowError)?;
+        Ok(())
+    }
+}
+
+// Collateral accounts used to be seeded by the user only, which limited every user to a single
+// collateral mint. This moves the legacy ledger and pool to the per-mint accounts and closes them,
+// on top of anything already deposited in the per-mint pool.
+// Legacy pools owned by the user themselves (older versions) are migrated as well.
+#[derive(Accounts)]
+pub struct MigrateCollateralAccount<'info> {
+    #[account(mut)]
+    pub user: Signer<'info>,
+
+    #[account(seeds = [b"protocol_config"], bump = protocol_config.bump)]
+    pub protocol_config: Account<'info, ProtocolConfig>,
+
+    /// CHECK: Legacy ledger, its owner and discriminator are checked in the handler
+    #[account(
+        mut,
+        seeds = [b"user_collateral", user.key().as_ref()],
+        bump
+    )]
+    pub legacy_user_collateral: UncheckedAccount<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"collateral_token_account", user.key().as_ref()],
+        bump
+    )]
+    pub legacy_collateral_token_account: Account<'info, TokenAccount>,
+
+    #[account(
+        address = legacy_collateral_token_account.mint @ LoanError::InvalidMint
+    )]
+    pub mint: Account<'info, Mint>,
+
+    #[account(
+        init_if_needed,
+        payer = user,
+        space = 8 + std::mem::size_of::<UserCollateral>(),
+        seeds = [b"user_collateral", user.key().as_ref(), mint.key().as_ref()],
+        bump
+    )]
+    pub user_collateral: Account<'info, UserCollateral>,
+
+    #[account(
+        init_if_needed,
+        payer = user,
+        token::mint = mint,
+        token::authority = user_collateral,
+        seeds = [b"collateral_token_account", user.key().as_ref(), mint.key().as_ref()],
+        bump
+    )]
+    pub collateral_token_account: Account<'info, TokenAccount>,
+
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+    pub rent: Sysvar<'info, Rent>,
+}
+
+// Never paused, legacy accounts have to be migrated before their collateral can be withdrawn
+pub fn migrate_collateral_account(ctx: Context<MigrateCollateralAccount>) -> Result<()> {
+    let legacy_ledger = ctx.accounts.legacy_user_collateral.to_account_info();
+    require!(legacy_ledger.owner == ctx.program_id, LoanError::InvalidOwner);
+
+    let legacy_pool = &ctx.accounts.legacy_collateral_token_account;
+    let legacy = LegacyUserCollateral::decode(&legacy_ledger.try_borrow_data()?, legacy_pool.amount)?;
+    require!(legacy.user == ctx.accounts.user.key(), LoanError::InvalidOwner);
+
+    // The legacy pool is owned either by the legacy ledger PDA or, for the oldest accounts, by the user
+    let user_key = ctx.accounts.user.key();
+    let authority = if legacy_pool.owner == user_key {
+        ctx.accounts.user.to_account_info()
+    } else {
+        require!(legacy_pool.owner == legacy_ledger.key(), LoanError::InvalidTokenAccountOwner);
+        legacy_ledger.clone()
+    };
+    let legacy_seeds = &[
+        b"user_collateral",
+        user_key.as_ref(),
+        &[ctx.bumps.legacy_user_collateral],
+    ];
+    let signer = &[&legacy_seeds[..]];
+
+    // Move the pooled tokens to the per-mint pool, then close the legacy pool
+    if legacy_pool.amount > 0 {
+        let cpi_accounts = Transfer {
+            from: legacy_pool.to_account_info(),
+            to: ctx.accounts.collateral_token_account.to_account_info(),
+            authority: authority.clone(),
+        };
+        let cpi_program = ctx.accounts.token_program.to_account_info();
+        let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
+        token::transfer(cpi_ctx, legacy_pool.amount)?;
+    }
+
+    let cpi_accounts = CloseAccount {
+        account: legacy_pool.to_account_info(),
+        destination: ctx.accounts.user.to_account_info(),
+        authority,
+    };
+    let cpi_program = ctx.accounts.token_program.to_account_info();
+    let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
+    token::close_account(cpi_ctx)?;
+
+    // Carry the balances over to the per-mint ledger
+    let user_collateral = &mut ctx.accounts.user_collateral;
+    if user_collateral.user == Pubkey::default() {
+        user_collateral.user = user_key;
+        user_collateral.mint = ctx.accounts.mint.key();
+        user_collateral.free = 0;
+        user_collateral.locked = 0;
+        user_collateral.active_loans = 0;
+    }
+    legacy.add_to(user_collateral)?;
+
+    // Close the legacy ledger and refund its rent to the user
+    let user_info = ctx.accounts.user.to_account_info();
+    let refund = legacy_ledger.lamports();
+    **user_info.try_borrow_mut_lamports()? = user_info.lamports()
+        .checked_add(refund)
+        .ok_or(LoanError::OverflowError)?;
+    **legacy_ledger.try_borrow_mut_lamports()? = 0;
+    legacy_ledger.assign(&system_program::ID);
+    legacy_ledger.realloc(0, false)?;
+
+    msg!("Collateral of {:?} migrated to the {:?} pool", user_key, ctx.accounts.mint.key());
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ledger(payload: &[u64]) -> (Pubkey, Vec<u8>) {
+        let user = Pubkey::new_unique();
+        let mut data = UserCollateral::DISCRIMINATOR.to_vec();
+        data.extend_from_slice(user.as_ref());
+        for value in payload {
+            data.extend_from_slice(&value.to_le_bytes());
+        }
+        (user, data)
+    }
+
+    #[test]
+    fn baseline_ledger_migrates_as_free_collateral() {
+        let (user, data) = ledger(&[7_000]);
+        assert_eq!(data.len(), 8 + std::mem::size_of::<BaselineUserCollateral>());
+
+        let legacy = LegacyUserCollateral::decode(&data, 7_000).unwrap();
+        assert_eq!(legacy.user, user);
+        assert_eq!(legacy.free, 7_000);
+        assert_eq!(legacy.locked, 0);
+        assert_eq!(legacy.active_loans, 0);
+    }
+
+    #[test]
+    fn baseline_pledges_left_in_the_pool_become_free() {
+        // 3000 were pledged to a loan, subtracted from the balance but still in the pool
+        let (_, data) = ledger(&[7_000]);
+
+        let legacy = LegacyUserCollateral::decode(&data, 10_000).unwrap();
+        assert_eq!(legacy.free, 10_000);
+        assert_eq!(legacy.locked, 0);
+    }
+
+    #[test]
+    fn legacy_ledger_keeps_its_balances() {
+        let (user, data) = ledger(&[5_000, 2_000, 1]);
+
+        let legacy = LegacyUserCollateral::decode(&data, 5_000).unwrap();
+        assert_eq!(legacy.user, user);
+        assert_eq!(legacy.free, 5_000);
+        assert_eq!(legacy.locked, 2_000);
+        assert_eq!(legacy.active_loans, 1);
+    }
+
+    #[test]
+    fn legacy_balances_are_added_to_existing_deposits() {
+        let (user, data) = ledger(&[5_000, 2_000, 1]);
+        let legacy = LegacyUserCollateral::decode(&data, 5_000).unwrap();
+
+        // Collateral deposited under the per-mint accounts before migrating
+        let mut ledger = UserCollateral {
+            user,
+            mint: Pubkey::new_unique(),
+            free: 1_000,
+            locked: 3_000,
+            active_loans: 2,
+        };
+        legacy.add_to(&mut ledger).unwrap();
+        assert_eq!(ledger.free, 6_000);
+        assert_eq!(ledger.locked, 5_000);
+        assert_eq!(ledger.active_loans, 3);
+
+        ledger.free = u64::MAX;
+        assert!(legacy.add_to(&mut ledger).is_err());
+    }
+
+    #[test]
+    fn unknown_layouts_are_rejected() {
+        let (_, data) = ledger(&[5_000, 2_000]);
+        assert!(LegacyUserCollateral::decode(&data, 7_000).is_err());
+
+        // The pool can't hold less than the balance of a baseline ledger
+        let (_, data) = ledger(&[7_000]);
+        assert!(LegacyUserCollateral::decode(&data, 6_999).is_err());
+
+        let (_, mut data) = ledger(&[7_000]);
+        data[0] ^= 1;
+        assert!(LegacyUserCollateral::decode(&data, 7_000).is_err());
+    }
+}
@@ -0,0 +1,67 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token::Mint;
+use crate::states::{MintConfig, ProtocolConfig};
+use crate::models::MintConfigured;
+use crate::errors::LoanError;
+use crate::math::BPS_DENOMINATOR;
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct MintSettings {
+    pub allow_loan: bool,
+    pub allow_collateral: bool,
+    pub collateral_haircut_bps: u64,
+    pub price_feed: Option<Pubkey>,
+    pub max_total_borrowed: u64,
+}
+
+// Create or update the settings of a mint, only the admin can do this
+#[derive(Accounts)]
+pub struct ConfigureMint<'info> {
+    #[account(mut)]
+    pub admin: Signer<'info>,
+
+    #[account(
+        seeds = [b"protocol_config"],
+        bump = protocol_config.bump,
+        has_one = admin @ LoanError::Unauthorized
+    )]
+    pub protocol_config: Account<'info, ProtocolConfig>,
+
+    pub mint: Account<'info, Mint>,
+
+    #[account(
+        init_if_needed,
+        payer = admin,
+        space = 8 + std::mem::size_of::<MintConfig>(),
+        seeds = [b"mint_config", mint.key().as_ref()],
+        bump
+    )]
+    pub mint_config: Account<'info, MintConfig>,
+
+    pub system_program: Program<'info, System>,
+}
+
+pub fn configure_mint(ctx: Context<ConfigureMint>, settings: MintSettings) -> Result<()> {
+    require!(settings.collateral_haircut_bps < BPS_DENOMINATOR, LoanError::InvalidProtocolSettings);
+
+    let mint_config = &mut ctx.accounts.mint_config;
+    mint_config.mint = ctx.accounts.mint.key();
+    mint_config.allow_loan = settings.allow_loan;
+    mint_config.allow_collateral = settings.allow_collateral;
+    mint_config.collateral_haircut_bps = settings.collateral_haircut_bps;
+    mint_config.price_feed = settings.price_feed;
+    mint_config.max_total_borrowed = settings.max_total_borrowed;
+    mint_config.decimals = ctx.accounts.mint.decimals;
+    mint_config.bump = ctx.bumps.mint_config;
+
+    emit!(MintConfigured {
+        mint: mint_config.mint,
+        allow_loan: settings.allow_loan,
+        allow_collateral: settings.allow_collateral,
+        collateral_haircut_bps: settings.collateral_haircut_bps,
+        price_feed: settings.price_feed,
+        max_total_borrowed: settings.max_total_borrowed,
+    });
+
+    Ok(())
+}
@@ -0,0 +1,76 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token::Mint;
+use crate::states::{MockPriceFeed, ProtocolConfig};
+use crate::errors::LoanError;
+
+// Price feed written by its authority, meant for local validators and tests where no
+// real oracle is available
+#[derive(Accounts)]
+pub struct InitializeMockPriceFeed<'info> {
+    #[account(mut)]
+    pub authority: Signer<'info>,
+
+    // Only the admin can publish mock prices
+    #[account(
+        seeds = [b"protocol_config"],
+        bump = protocol_config.bump,
+        constraint = protocol_config.admin == authority.key() @ LoanError::Unauthorized
+    )]
+    pub protocol_config: Account<'info, ProtocolConfig>,
+
+    pub mint: Account<'info, Mint>,
+
+    #[account(
+        init,
+        payer = authority,
+        space = 8 + std::mem::size_of::<MockPriceFeed>(),
+        seeds = [b"mock_price_feed", authority.key().as_ref(), mint.key().as_ref()],
+        bump
+    )]
+    pub price_feed: Account<'info, MockPriceFeed>,
+
+    pub system_program: Program<'info, System>,
+}
+
+pub fn initialize_mock_price_feed(ctx: Context<InitializeMockPriceFeed>, price: u64, expo: i32) -> Result<()> {
+    require!(price > 0, LoanError::InvalidPrice);
+
+    let price_feed = &mut ctx.accounts.price_feed;
+    price_feed.authority = ctx.accounts.authority.key();
+    price_feed.mint = ctx.accounts.mint.key();
+    price_feed.price = price;
+    price_feed.expo = expo;
+    price_feed.publish_time = Clock::get()?.unix_timestamp;
+
+    Ok(())
+}
+
+#[derive(Accounts)]
+pub struct SetMockPrice<'info> {
+    pub authority: Signer<'info>,
+
+    // The feed stays with the admin who created it, a former admin can no longer move the price
+    #[account(
+        seeds = [b"protocol_config"],
+        bump = protocol_config.bump,
+        constraint = protocol_config.admin == authority.key() @ LoanError::Unauthorized
+    )]
+    pub protocol_config: Account<'info, ProtocolConfig>,
+
+    #[account(
+        mut,
+        has_one = authority @ LoanError::InvalidOwner
+    )]
+    pub price_feed: Account<'info, MockPriceFeed>,
+}
+
+pub fn set_mock_price(ctx: Context<SetMockPrice>, price: u64, expo: i32) -> Result<()> {
+    require!(price > 0, LoanError::InvalidPrice);
+
+    let price_feed = &mut ctx.accounts.price_feed;
+    price_feed.price = price;
+    price_feed.expo = expo;
+    price_feed.publish_time = Clock::get()?.unix_timestamp;
+
+    Ok(())
+}
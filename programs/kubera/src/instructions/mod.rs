@@ -5,6 +5,16 @@ pub mod guarantor_offer;
 pub mod withdraw_collateral;
 pub mod cancel_loan_offer;
 pub mod repay_loan;
+pub mod claim_default;
+pub mod migrate_collateral;
+pub mod expire_loan_offer;
+pub mod quote_repayment;
+pub mod mock_price_feed;
+pub mod liquidate;
+pub mod collateral_auction;
+pub mod protocol_config;
+pub mod treasury;
+pub mod mint_config;
 
 pub use create_loan_offer::*;
 pub use accept_loan::*;
@@ -12,4 +22,14 @@ pub use deposit_collateral::*;
 pub use guarantor_offer::*; 
 pub use withdraw_collateral::*;
 pub use cancel_loan_offer::*;
-pub use repay_loan::*;
\ No newline at end of file
+pub use repay_loan::*;
+pub use claim_default::*;
+pub use migrate_collateral::*;
+pub use expire_loan_offer::*;
+pub use quote_repayment::*;
+pub use mock_price_feed::*;
+pub use liquidate::*;
+pub use collateral_auction::*;
+pub use protocol_config::*;
+pub use treasury::*;
+pub use mint_config::*;
\ No newline at end of file
@@ -0,0 +1,152 @@
+use anchor_lang::prelude::*;
+use crate::program::Kubera;
+use crate::states::ProtocolConfig;
+use crate::models::{AdminTransferProposed, AdminTransferred, PauseFlags, PauseFlagsUpdated, ProtocolSettingsUpdated};
+use crate::errors::LoanError;
+use crate::math::BPS_DENOMINATOR;
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct ProtocolSettings {
+    pub origination_fee_bps: u64,
+    pub interest_fee_bps: u64,
+    pub max_loan_amount: u64,
+    pub max_term: i64,
+    pub max_ltv_bps: u64,
+}
+
+impl ProtocolSettings {
+    fn validate(&self) -> Result<()> {
+        require!(
+            self.origination_fee_bps <= BPS_DENOMINATOR
+                && self.interest_fee_bps <= BPS_DENOMINATOR
+                && self.max_term >= 0
+                && self.max_ltv_bps <= BPS_DENOMINATOR,
+            LoanError::InvalidProtocolSettings
+        );
+        Ok(())
+    }
+
+    fn apply(&self, config: &mut ProtocolConfig) {
+        config.origination_fee_bps = self.origination_fee_bps;
+        config.interest_fee_bps = self.interest_fee_bps;
+        config.max_loan_amount = self.max_loan_amount;
+        config.max_term = self.max_term;
+        config.max_ltv_bps = self.max_ltv_bps;
+    }
+}
+
+// Only the upgrade authority of the program can create the config, which makes it the first admin
+#[derive(Accounts)]
+pub struct InitializeProtocol<'info> {
+    #[account(mut)]
+    pub admin: Signer<'info>,
+
+    #[account(
+        init,
+        payer = admin,
+        space = 8 + std::mem::size_of::<ProtocolConfig>(),
+        seeds = [b"protocol_config"],
+        bump
+    )]
+    pub protocol_config: Account<'info, ProtocolConfig>,
+
+    #[account(constraint = program.programdata_address()? == Some(program_data.key()) @ LoanError::Unauthorized)]
+    pub program: Program<'info, Kubera>,
+
+    #[account(constraint = program_data.upgrade_authority_address == Some(admin.key()) @ LoanError::Unauthorized)]
+    pub program_data: Account<'info, ProgramData>,
+
+    pub system_program: Program<'info, System>,
+}
+
+pub fn initialize_protocol(ctx: Context<InitializeProtocol>, settings: ProtocolSettings) -> Result<()> {
+    settings.validate()?;
+
+    let config = &mut ctx.accounts.protocol_config;
+    config.admin = ctx.accounts.admin.key();
+    config.pending_admin = None;
+    config.pause = PauseFlags::default();
+    config.bump = ctx.bumps.protocol_config;
+    settings.apply(config);
+
+    Ok(())
+}
+
+#[derive(Accounts)]
+pub struct UpdateProtocol<'info> {
+    pub admin: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"protocol_config"],
+        bump = protocol_config.bump,
+        has_one = admin @ LoanError::Unauthorized
+    )]
+    pub protocol_config: Account<'info, ProtocolConfig>,
+}
+
+pub fn update_protocol_settings(ctx: Context<UpdateProtocol>, settings: ProtocolSettings) -> Result<()> {
+    settings.validate()?;
+    settings.apply(&mut ctx.accounts.protocol_config);
+
+    emit!(ProtocolSettingsUpdated {
+        origination_fee_bps: settings.origination_fee_bps,
+        interest_fee_bps: settings.interest_fee_bps,
+        max_loan_amount: settings.max_loan_amount,
+        max_term: settings.max_term,
+        max_ltv_bps: settings.max_ltv_bps,
+    });
+
+    Ok(())
+}
+
+pub fn set_pause_flags(ctx: Context<UpdateProtocol>, flags: PauseFlags) -> Result<()> {
+    ctx.accounts.protocol_config.pause = flags;
+
+    emit!(PauseFlagsUpdated {
+        admin: ctx.accounts.admin.key(),
+        flags,
+    });
+
+    Ok(())
+}
+
+// First step of the admin transfer, the new admin has to accept before it takes over
+pub fn propose_admin(ctx: Context<UpdateProtocol>, new_admin: Pubkey) -> Result<()> {
+    let config = &mut ctx.accounts.protocol_config;
+    config.pending_admin = Some(new_admin);
+
+    emit!(AdminTransferProposed {
+        admin: config.admin,
+        pending_admin: new_admin,
+    });
+
+    Ok(())
+}
+
+#[derive(Accounts)]
+pub struct AcceptAdmin<'info> {
+    pub new_admin: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"protocol_config"],
+        bump = protocol_config.bump,
+        constraint = protocol_config.pending_admin == Some(new_admin.key()) @ LoanError::Unauthorized
+    )]
+    pub protocol_config: Account<'info, ProtocolConfig>,
+}
+
+pub fn accept_admin(ctx: Context<AcceptAdmin>) -> Result<()> {
+    let config = &mut ctx.accounts.protocol_config;
+    let previous_admin = config.admin;
+    config.admin = ctx.accounts.new_admin.key();
+    config.pending_admin = None;
+
+    emit!(AdminTransferred {
+        previous_admin,
+        new_admin: config.admin,
+    });
+
+    Ok(())
+}
@@ -0,0 +1,33 @@
+use anchor_lang::prelude::*;
+use crate::states::Loan;
+use crate::models::{LoanStatus, RepaymentQuote};
+use crate::errors::LoanError;
+
+#[derive(Accounts)]
+pub struct QuoteRepayment<'info> {
+    #[account(
+        constraint = loan.status == LoanStatus::Active @ LoanError::LoanNotActive,
+    )]
+    pub loan: Account<'info, Loan>,
+}
+
+// Read-only, returns what the borrower owes right now without touching the loan account
+pub fn quote_repayment(ctx: Context<QuoteRepayment>) -> Result<RepaymentQuote> {
+    let now = Clock::get()?.unix_timestamp;
+
+    // Accrue on a copy so the quote follows exactly the same logic as `repay_loan`
+    let mut loan = (*ctx.accounts.loan).clone();
+    loan.accrue_interest(now)?;
+    loan.accrue_penalty(now)?;
+
+    Ok(RepaymentQuote {
+        loan_id: loan.loan_id,
+        amount_due: loan.amount_due()?,
+        principal_outstanding: loan.principal_outstanding,
+        interest_due: loan.interest_owed(),
+        penalty_due: loan.penalty_outstanding,
+        installment_due: loan.installment_due()?,
+        next_due_date: loan.next_due_date,
+        quoted_at: now,
+    })
+}
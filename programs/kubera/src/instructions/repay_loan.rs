@@ -1,14 +1,18 @@
 use anchor_lang::prelude::*;
-use anchor_spl::token::{self, Token, TokenAccount, Transfer};
-use crate::states::Loan;
-use crate::models::{LoanStatus, LoanRepaid};
+use anchor_spl::token::{self, Token, TokenAccount, Transfer, CloseAccount};
+use crate::states::{Loan, UserCollateral, MintConfig, ProtocolConfig};
+use crate::models::{LoanStatus, LoanRepaid, GuarantorPaid, InterestFeeCollected};
 use crate::errors::LoanError;
+use crate::math::{self, Rounding};
 
 #[derive(Accounts)]
 pub struct RepayLoan<'info> {
     #[account(mut)]
     pub borrower: Signer<'info>,
 
+    #[account(seeds = [b"protocol_config"], bump = protocol_config.bump)]
+    pub protocol_config: Account<'info, ProtocolConfig>,
+
     #[account(
         mut,
         constraint = loan.status == LoanStatus::Active @ LoanError::LoanNotActive,
@@ -16,12 +20,78 @@ pub struct RepayLoan<'info> {
     )]
     pub loan: Account<'info, Loan>,
 
-    #[account(mut, constraint = borrower_token_account.owner == borrower.key())]
+    #[account(
+        mut,
+        seeds = [b"user_collateral", borrower.key().as_ref(), loan.collateral_mint.as_ref()],
+        bump
+    )]
+    pub borrower_collateral: Account<'info, UserCollateral>,
+
+    #[account(
+        mut,
+        constraint = guarantor_collateral.mint == loan.collateral_mint @ LoanError::InvalidMint
+    )]
+    pub guarantor_collateral: Option<Account<'info, UserCollateral>>,
+
+    #[account(
+        mut,
+        seeds = [b"collateral_token_account", borrower.key().as_ref(), loan.collateral_mint.as_ref()],
+        bump,
+        constraint = collateral_token_account.mint == loan.collateral_mint @ LoanError::InvalidMint
+    )]
+    pub collateral_token_account: Account<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        constraint = guarantor_collateral_token_account.mint == loan.collateral_mint @ LoanError::InvalidMint
+    )]
+    pub guarantor_collateral_token_account: Option<Account<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        seeds = [b"collateral_vault", loan.key().as_ref()],
+        bump,
+        constraint = collateral_vault.mint == loan.collateral_mint @ LoanError::InvalidMint
+    )]
+    pub collateral_vault: Account<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        constraint = borrower_token_account.owner == borrower.key(),
+        constraint = borrower_token_account.mint == loan.loan_mint @ LoanError::InvalidMint
+    )]
     pub borrower_token_account: Account<'info, TokenAccount>,
 
-    #[account(mut, constraint = lender_token_account.owner == loan.lender)]
+    #[account(
+        mut,
+        constraint = lender_token_account.owner == loan.lender,
+        constraint = lender_token_account.mint == loan.loan_mint @ LoanError::InvalidMint
+    )]
     pub lender_token_account: Account<'info, TokenAccount>,
 
+    #[account(
+        mut,
+        constraint = Some(guarantor_token_account.owner) == loan.guarantor @ LoanError::InvalidTokenAccountOwner,
+        constraint = guarantor_token_account.mint == loan.loan_mint @ LoanError::InvalidMint
+    )]
+    pub guarantor_token_account: Option<Account<'info, TokenAccount>>,
+
+    // Principal leaving the loan no longer counts against the borrow cap of the mint
+    #[account(
+        mut,
+        seeds = [b"mint_config", loan.loan_mint.as_ref()],
+        bump = loan_mint_config.bump
+    )]
+    pub loan_mint_config: Account<'info, MintConfig>,
+
+    // Receives the protocol's cut of the interest
+    #[account(
+        mut,
+        seeds = [b"treasury", loan.loan_mint.as_ref()],
+        bump
+    )]
+    pub treasury_vault: Account<'info, TokenAccount>,
+
     pub token_program: Program<'info, Token>,
     pub clock: Sysvar<'info, Clock>,
 }
@@ -30,24 +100,23 @@ pub fn repay_loan(ctx: Context<RepayLoan>, amount: u64) -> Result<()> {
     let loan = &mut ctx.accounts.loan;
     let clock = &ctx.accounts.clock;
 
-    // Calculate loan expiry time
-    let expiry_time = loan.start_time.checked_add(loan.term).unwrap();
+    // Bring the accrued interest and late-payment penalty up to date
+    loan.accrue_interest(clock.unix_timestamp)?;
+    loan.accrue_penalty(clock.unix_timestamp)?;
 
-    // Check if the loan has expired
-    let is_expired = clock.unix_timestamp >= expiry_time;
+    // Partial repayments are allowed at any time, `amount` is capped at the balance due
+    let amount_due = loan.amount_due()?;
+    let amount = amount.min(amount_due);
+    require!(amount > 0, LoanError::InvalidAmount);
+    let (penalty_paid, interest_paid, principal_paid) = loan.apply_repayment(amount)?;
+    let remaining_balance = loan.amount_due()?;
 
-    // Calculate total repayment amount (principal + interest)
-    let total_repayment = loan.amount.checked_add(
-        loan.amount.checked_mul(loan.interest_rate).unwrap().checked_div(10000).unwrap()
-    ).unwrap();
+    // Apply the payment to the installment schedule
+    loan.advance_installments(clock.unix_timestamp)?;
+    ctx.accounts.loan_mint_config.release_borrowed(principal_paid);
 
-    // For repayments before expiry, ensure the amount is sufficient
-    if !is_expired {
-        require!(amount >= total_repayment, LoanError::InsufficientRepayment);
-    } else {
-        // For expired loans, allow partial repayments
-        require!(amount <= total_repayment, LoanError::ExcessiveRepayment);
-    }
+    // The protocol takes its cut of the interest, the lender gets the rest
+    let interest_fee = math::apply_bps(interest_paid, ctx.accounts.protocol_config.interest_fee_bps, Rounding::Down)?;
 
     // Transfer tokens from borrower to lender
     let cpi_accounts = Transfer {
@@ -57,18 +126,179 @@ pub fn repay_loan(ctx: Context<RepayLoan>, amount: u64) -> Result<()> {
     };
     let cpi_program = ctx.accounts.token_program.to_account_info();
     let cpi_ctx = CpiContext::new(cpi_program, cpi_accounts);
-    token::transfer(cpi_ctx, amount)?;
+    token::transfer(cpi_ctx, amount - interest_fee)?;
 
-    // Update loan status if fully repaid
-    if amount == total_repayment {
+    if interest_fee > 0 {
+        let cpi_accounts = Transfer {
+            from: ctx.accounts.borrower_token_account.to_account_info(),
+            to: ctx.accounts.treasury_vault.to_account_info(),
+            authority: ctx.accounts.borrower.to_account_info(),
+        };
+        let cpi_program = ctx.accounts.token_program.to_account_info();
+        let cpi_ctx = CpiContext::new(cpi_program, cpi_accounts);
+        token::transfer(cpi_ctx, interest_fee)?;
+
+        emit!(InterestFeeCollected {
+            loan_id: loan.loan_id,
+            mint: loan.loan_mint,
+            amount: interest_fee,
+        });
+    }
+
+    // Update loan status and release the locked collateral once fully repaid
+    if remaining_balance == 0 {
         loan.status = LoanStatus::Repaid;
+        pay_guarantor_fee(&ctx)?;
+        release_collateral(&ctx)?;
+
+        let loan = &ctx.accounts.loan;
+        ctx.accounts.borrower_collateral.release(loan.borrower_contribution, 0)?;
+
+        if loan.guarantor_contribution > 0 {
+            if let Some(guarantor_collateral) = ctx.accounts.guarantor_collateral.as_mut() {
+                guarantor_collateral.release(loan.guarantor_contribution, 0)?;
+            }
+        }
     }
 
+    let loan = &ctx.accounts.loan;
+
     // Emit an event for the repayment
     emit!(LoanRepaid {
         loan_id: loan.loan_id,
         amount_repaid: amount,
+        interest_paid,
+        principal_paid,
+        penalty_paid,
+        remaining_balance,
+        next_due_date: loan.next_due_date,
     });
 
     Ok(())
-}
\ No newline at end of file
+}
+
+// Drain the loan vault back into the borrower's and guarantor's collateral accounts, then close it
+fn release_collateral(ctx: &Context<RepayLoan>) -> Result<()> {
+    let loan = &ctx.accounts.loan;
+    let vault = &ctx.accounts.collateral_vault;
+
+    let mut borrower_share = vault.amount;
+
+    if loan.guarantor_contribution > 0 {
+        let guarantor_collateral = ctx.accounts.guarantor_collateral
+            .as_ref()
+            .ok_or(LoanError::GuarantorNotProvided)?;
+        let guarantor_collateral_token_account = ctx.accounts.guarantor_collateral_token_account
+            .as_ref()
+            .ok_or(LoanError::GuarantorNotProvided)?;
+        require!(Some(guarantor_collateral.user) == loan.guarantor, LoanError::InvalidOwner);
+
+        // Verify that the guarantor's collateral token account is the PDA owned by the guarantor
+        let (guarantor_pool, _) = Pubkey::find_program_address(
+            &[
+                b"collateral_token_account",
+                guarantor_collateral.user.as_ref(),
+                guarantor_collateral.mint.as_ref()
+            ],
+            ctx.program_id
+        );
+        require!(guarantor_pool == guarantor_collateral_token_account.key(), LoanError::InvalidPDA);
+
+        transfer_from_vault(
+            loan,
+            vault,
+            guarantor_collateral_token_account.to_account_info(),
+            &ctx.accounts.token_program,
+            loan.guarantor_contribution,
+        )?;
+
+        borrower_share = borrower_share
+            .checked_sub(loan.guarantor_contribution)
+            .ok_or(LoanError::InsufficientCollateral)?;
+    }
+
+    transfer_from_vault(
+        loan,
+        vault,
+        ctx.accounts.collateral_token_account.to_account_info(),
+        &ctx.accounts.token_program,
+        borrower_share,
+    )?;
+
+    // Refund the vault rent to the borrower who paid for it
+    close_vault(loan, vault, ctx.accounts.borrower.to_account_info(), &ctx.accounts.token_program)
+}
+
+// Move collateral out of the loan vault, which is owned by the loan PDA
+pub(crate) fn transfer_from_vault<'info>(
+    loan: &Account<'info, Loan>,
+    vault: &Account<'info, TokenAccount>,
+    to: AccountInfo<'info>,
+    token_program: &Program<'info, Token>,
+    amount: u64,
+) -> Result<()> {
+    if amount == 0 {
+        return Ok(());
+    }
+
+    let loan_seeds = loan.signer_seeds();
+    let signer = &[&loan_seeds[..]];
+    let cpi_accounts = Transfer {
+        from: vault.to_account_info(),
+        to,
+        authority: loan.to_account_info(),
+    };
+    let cpi_ctx = CpiContext::new_with_signer(token_program.to_account_info(), cpi_accounts, signer);
+    token::transfer(cpi_ctx, amount)
+}
+
+// Close the emptied loan vault and refund its rent to `destination`
+pub(crate) fn close_vault<'info>(
+    loan: &Account<'info, Loan>,
+    vault: &Account<'info, TokenAccount>,
+    destination: AccountInfo<'info>,
+    token_program: &Program<'info, Token>,
+) -> Result<()> {
+    let loan_seeds = loan.signer_seeds();
+    let signer = &[&loan_seeds[..]];
+    let cpi_accounts = CloseAccount {
+        account: vault.to_account_info(),
+        destination,
+        authority: loan.to_account_info(),
+    };
+    let cpi_ctx = CpiContext::new_with_signer(token_program.to_account_info(), cpi_accounts, signer);
+    token::close_account(cpi_ctx)
+}
+
+// The borrower pays the guarantor's fee, the guarantor's interest rate applied to the
+// share of the principal backed by the guarantor's collateral
+fn pay_guarantor_fee(ctx: &Context<RepayLoan>) -> Result<()> {
+    let loan = &ctx.accounts.loan;
+    if loan.guarantor_contribution == 0 || loan.guarantor_interest_rate == 0 {
+        return Ok(());
+    }
+
+    let backed_principal = math::mul_div(loan.amount, loan.guarantor_contribution, loan.collateral, Rounding::Up)?;
+    let fee = math::apply_bps(backed_principal, loan.guarantor_interest_rate, Rounding::Up)?;
+
+    let guarantor_token_account = ctx.accounts.guarantor_token_account
+        .as_ref()
+        .ok_or(LoanError::GuarantorNotProvided)?;
+
+    let cpi_accounts = Transfer {
+        from: ctx.accounts.borrower_token_account.to_account_info(),
+        to: guarantor_token_account.to_account_info(),
+        authority: ctx.accounts.borrower.to_account_info(),
+    };
+    let cpi_program = ctx.accounts.token_program.to_account_info();
+    let cpi_ctx = CpiContext::new(cpi_program, cpi_accounts);
+    token::transfer(cpi_ctx, fee)?;
+
+    emit!(GuarantorPaid {
+        loan_id: loan.loan_id,
+        guarantor: guarantor_token_account.owner,
+        amount: fee,
+    });
+
+    Ok(())
+}
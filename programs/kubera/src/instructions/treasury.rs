@@ -0,0 +1,90 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token::{self, Token, TokenAccount, Mint, Transfer};
+use crate::states::ProtocolConfig;
+use crate::models::TreasuryWithdrawn;
+use crate::errors::LoanError;
+
+// Protocol fees are collected in one vault per mint, owned by the protocol config PDA
+#[derive(Accounts)]
+pub struct InitializeTreasury<'info> {
+    #[account(mut)]
+    pub admin: Signer<'info>,
+
+    #[account(
+        seeds = [b"protocol_config"],
+        bump = protocol_config.bump,
+        has_one = admin @ LoanError::Unauthorized
+    )]
+    pub protocol_config: Account<'info, ProtocolConfig>,
+
+    pub mint: Account<'info, Mint>,
+
+    #[account(
+        init,
+        payer = admin,
+        token::mint = mint,
+        token::authority = protocol_config,
+        seeds = [b"treasury", mint.key().as_ref()],
+        bump
+    )]
+    pub treasury_vault: Account<'info, TokenAccount>,
+
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+    pub rent: Sysvar<'info, Rent>,
+}
+
+pub fn initialize_treasury(ctx: Context<InitializeTreasury>) -> Result<()> {
+    msg!("Treasury for {:?} created at {:?}", ctx.accounts.mint.key(), ctx.accounts.treasury_vault.key());
+    Ok(())
+}
+
+#[derive(Accounts)]
+pub struct WithdrawTreasury<'info> {
+    pub admin: Signer<'info>,
+
+    #[account(
+        seeds = [b"protocol_config"],
+        bump = protocol_config.bump,
+        has_one = admin @ LoanError::Unauthorized
+    )]
+    pub protocol_config: Account<'info, ProtocolConfig>,
+
+    #[account(
+        mut,
+        seeds = [b"treasury", treasury_vault.mint.as_ref()],
+        bump
+    )]
+    pub treasury_vault: Account<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        constraint = destination.mint == treasury_vault.mint @ LoanError::InvalidMint
+    )]
+    pub destination: Account<'info, TokenAccount>,
+
+    pub token_program: Program<'info, Token>,
+}
+
+pub fn withdraw_treasury(ctx: Context<WithdrawTreasury>, amount: u64) -> Result<()> {
+    require!(amount > 0 && amount <= ctx.accounts.treasury_vault.amount, LoanError::InvalidAmount);
+
+    let config_seeds = &[b"protocol_config".as_ref(), &[ctx.accounts.protocol_config.bump]];
+    let signer = &[&config_seeds[..]];
+    let cpi_accounts = Transfer {
+        from: ctx.accounts.treasury_vault.to_account_info(),
+        to: ctx.accounts.destination.to_account_info(),
+        authority: ctx.accounts.protocol_config.to_account_info(),
+    };
+    let cpi_program = ctx.accounts.token_program.to_account_info();
+    let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
+    token::transfer(cpi_ctx, amount)?;
+
+    emit!(TreasuryWithdrawn {
+        mint: ctx.accounts.treasury_vault.mint,
+        amount,
+        destination: ctx.accounts.destination.key(),
+    });
+
+    Ok(())
+}
@@ -10,52 +10,53 @@ pub struct WithdrawCollateral<'info> {
 
     #[account(
         mut,
-        seeds = [b"user_collateral", user.key().as_ref()],
+        seeds = [b"user_collateral", user.key().as_ref(), mint.key().as_ref()],
         bump
     )]
     pub user_collateral: Account<'info, UserCollateral>,
 
     #[account(
         mut,
-        constraint = user_token_account.owner == user.key()
+        constraint = user_token_account.owner == user.key(),
+        constraint = user_token_account.mint == collateral_token_account.mint @ LoanError::InvalidMint
     )]
     pub user_token_account: Account<'info, TokenAccount>,
 
     #[account(
         mut,
-        seeds = [b"collateral_token_account", user.key().as_ref()],
+        seeds = [b"collateral_token_account", user.key().as_ref(), mint.key().as_ref()],
         bump
     )]
     pub collateral_token_account: Account<'info, TokenAccount>,
 
+    #[account(
+        constraint = mint.key() == collateral_token_account.mint @ LoanError::InvalidMint
+    )]
     pub mint: Account<'info, Mint>,
     pub token_program: Program<'info, Token>,
     pub system_program: Program<'info, System>,
 }
 
+// The mint config is not checked, collateral can always be taken out of a disabled mint
 pub fn withdraw_collateral(ctx: Context<WithdrawCollateral>, amount: u64) -> Result<()> {
     let user_collateral = &mut ctx.accounts.user_collateral;
-    
-    // Check if the user has sufficient collateral
-    require!(user_collateral.amount >= amount, LoanError::InsufficientCollateral);
+
+    // Never paused. Only the free balance can be withdrawn, locked collateral backs active loans
+    require!(user_collateral.free >= amount, LoanError::InsufficientCollateral);
 
     // Update the collateral amount
-    user_collateral.amount = user_collateral.amount.checked_sub(amount).unwrap();
+    user_collateral.free = user_collateral.free.checked_sub(amount).ok_or(LoanError::InsufficientCollateral)?;
 
-    // Create a binding for the user's public key
+    // Create a binding for the user's public key and the collateral mint
     let user_pubkey = ctx.accounts.user.key();
+    let mint_pubkey = ctx.accounts.mint.key();
 
-    // Derive the PDA for the collateral token account
-    let (_, bump) = Pubkey::find_program_address(
-        &[b"collateral_token_account", user_pubkey.as_ref()],
-        ctx.program_id
-    );
-
-    // Create the seeds for signing
+    // The collateral token account is owned by the user_collateral PDA
     let seeds = &[
-        b"collateral_token_account",
+        b"user_collateral",
         user_pubkey.as_ref(),
-        &[bump],
+        mint_pubkey.as_ref(),
+        &[ctx.bumps.user_collateral],
     ];
     let signer = &[&seeds[..]];
 
@@ -63,7 +64,7 @@ pub fn withdraw_collateral(ctx: Context<WithdrawCollateral>, amount: u64) -> Res
     let cpi_accounts = Transfer {
         from: ctx.accounts.collateral_token_account.to_account_info(),
         to: ctx.accounts.user_token_account.to_account_info(),
-        authority: ctx.accounts.user.to_account_info(),
+        authority: ctx.accounts.user_collateral.to_account_info(),
     };
     let cpi_program = ctx.accounts.token_program.to_account_info();
     let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
@@ -3,6 +3,8 @@ use anchor_lang::prelude::*;
 pub mod models;
 pub mod errors;
 pub mod states;
+pub mod math;
+pub mod oracle;
 pub mod instructions;
 
 use instructions::withdraw_collateral::*;
@@ -12,6 +14,16 @@ use instructions::accept_loan::*;
 use instructions::deposit_collateral::*;
 use instructions::guarantor_offer::*;
 use instructions::repay_loan::*;
+use instructions::claim_default::*;
+use instructions::migrate_collateral::*;
+use instructions::expire_loan_offer::*;
+use instructions::quote_repayment::*;
+use instructions::mock_price_feed::*;
+use instructions::liquidate::*;
+use instructions::collateral_auction::*;
+use instructions::protocol_config::*;
+use instructions::treasury::*;
+use instructions::mint_config::*;
 
 // declare_id!("2gXdKnSrVRg9kB5xm5TTKBTvw6oLUs7axYdD1cfbm2cx");
 declare_id!("8h4QZ3TgpZBBBVaybKsXaRSEDMCjGsgrVR7xYs4BdHoU");
@@ -20,6 +32,38 @@ declare_id!("8h4QZ3TgpZBBBVaybKsXaRSEDMCjGsgrVR7xYs4BdHoU");
 pub mod kubera {
     use super::*;
 
+    pub fn initialize_protocol(ctx: Context<InitializeProtocol>, settings: ProtocolSettings) -> Result<()> {
+        instructions::protocol_config::initialize_protocol(ctx, settings)
+    }
+
+    pub fn update_protocol_settings(ctx: Context<UpdateProtocol>, settings: ProtocolSettings) -> Result<()> {
+        instructions::protocol_config::update_protocol_settings(ctx, settings)
+    }
+
+    pub fn set_pause_flags(ctx: Context<UpdateProtocol>, flags: models::PauseFlags) -> Result<()> {
+        instructions::protocol_config::set_pause_flags(ctx, flags)
+    }
+
+    pub fn propose_admin(ctx: Context<UpdateProtocol>, new_admin: Pubkey) -> Result<()> {
+        instructions::protocol_config::propose_admin(ctx, new_admin)
+    }
+
+    pub fn accept_admin(ctx: Context<AcceptAdmin>) -> Result<()> {
+        instructions::protocol_config::accept_admin(ctx)
+    }
+
+    pub fn configure_mint(ctx: Context<ConfigureMint>, settings: MintSettings) -> Result<()> {
+        instructions::mint_config::configure_mint(ctx, settings)
+    }
+
+    pub fn initialize_treasury(ctx: Context<InitializeTreasury>) -> Result<()> {
+        instructions::treasury::initialize_treasury(ctx)
+    }
+
+    pub fn withdraw_treasury(ctx: Context<WithdrawTreasury>, amount: u64) -> Result<()> {
+        instructions::treasury::withdraw_treasury(ctx, amount)
+    }
+
     pub fn initialize_loan_offer_counter(ctx: Context<InitializeLoanOfferCounter>) -> Result<()> {
         instructions::create_loan_offer::initialize_loan_offer_counter(ctx)
     }
@@ -28,16 +72,12 @@ pub mod kubera {
         instructions::create_loan_offer::create_loan_offer(ctx, args)
     }
 
-    pub fn transfer_to_escrow(ctx: Context<TransferToEscrow>, amount: u64) -> Result<()> {
-        instructions::create_loan_offer::transfer_to_escrow(ctx, amount)
-    }
-
     pub fn deposit_collateral(ctx: Context<DepositCollateral>, amount: u64) -> Result<()> {
         instructions::deposit_collateral::deposit_collateral(ctx, amount)
     }
 
-    pub fn accept_loan(ctx: Context<AcceptLoan>, loan_offer_id: u64, use_guarantor: bool) -> Result<()> {
-        instructions::accept_loan::accept_loan(ctx, loan_offer_id, use_guarantor)
+    pub fn accept_loan(ctx: Context<AcceptLoan>, loan_offer_id: u64, use_guarantor: bool, guarantor_offer_id: u64) -> Result<()> {
+        instructions::accept_loan::accept_loan(ctx, loan_offer_id, use_guarantor, guarantor_offer_id)
     }
     
     pub fn create_guarantor_offer(ctx: Context<CreateGuarantorOffer>, amount: u64, interest_rate: u64, expiry_date: i64) -> Result<()> {
@@ -55,10 +95,42 @@ pub mod kubera {
     pub fn cancel_loan_offer(ctx: Context<CancelLoanOffer>) -> Result<()> {
         instructions::cancel_loan_offer::cancel_loan_offer(ctx)
     }
+
+    pub fn expire_loan_offer(ctx: Context<ExpireLoanOffer>) -> Result<()> {
+        instructions::expire_loan_offer::expire_loan_offer(ctx)
+    }
     
     pub fn repay_loan(ctx: Context<RepayLoan>, amount: u64) -> Result<()> {
         instructions::repay_loan::repay_loan(ctx, amount)
     }
 
+    pub fn quote_repayment(ctx: Context<QuoteRepayment>) -> Result<models::RepaymentQuote> {
+        instructions::quote_repayment::quote_repayment(ctx)
+    }
+
+    pub fn claim_default(ctx: Context<ClaimDefault>) -> Result<()> {
+        instructions::claim_default::claim_default(ctx)
+    }
+
+    pub fn liquidate(ctx: Context<Liquidate>, repay_amount: u64) -> Result<()> {
+        instructions::liquidate::liquidate(ctx, repay_amount)
+    }
+
+    pub fn bid_collateral_auction(ctx: Context<BidCollateralAuction>, max_price: u64) -> Result<()> {
+        instructions::collateral_auction::bid_collateral_auction(ctx, max_price)
+    }
+
+    pub fn migrate_collateral_account(ctx: Context<MigrateCollateralAccount>) -> Result<()> {
+        instructions::migrate_collateral::migrate_collateral_account(ctx)
+    }
+
+    pub fn initialize_mock_price_feed(ctx: Context<InitializeMockPriceFeed>, price: u64, expo: i32) -> Result<()> {
+        instructions::mock_price_feed::initialize_mock_price_feed(ctx, price, expo)
+    }
+
+    pub fn set_mock_price(ctx: Context<SetMockPrice>, price: u64, expo: i32) -> Result<()> {
+        instructions::mock_price_feed::set_mock_price(ctx, price, expo)
+    }
+
 
 }
\ No newline at end of file
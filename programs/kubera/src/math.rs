@@ -0,0 +1,338 @@
+use anchor_lang::prelude::*;
+use crate::errors::LoanError;
+use crate::states::SECONDS_PER_YEAR;
+
+// Fixed-point interest math. Every intermediate is computed in u128 and every failure,
+// including a division by zero, returns `LoanError::OverflowError` instead of panicking.
+//
+// Rounding always favours the protocol: amounts owed by the borrower (interest, penalties,
+// fees) round up, amounts paid out of the protocol round down.
+
+// Rates are expressed in basis points
+pub const BPS_DENOMINATOR: u64 = 10_000;
+
+// 18-decimal fixed-point unit used for compounding factors
+pub const WAD: u128 = 1_000_000_000_000_000_000;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Rounding {
+    Down,
+    Up,
+}
+
+// value * numerator / denominator in u128. When value * numerator overflows, value is split
+// into a multiple of the denominator and a remainder so large WAD products still fit.
+pub fn mul_div_u128(value: u128, numerator: u128, denominator: u128, rounding: Rounding) -> Result<u128> {
+    require!(denominator != 0, LoanError::OverflowError);
+    let (quotient, remainder) = match value.checked_mul(numerator) {
+        Some(product) => (product / denominator, product % denominator),
+        None => {
+            let partial = (value % denominator).checked_mul(numerator).ok_or(LoanError::OverflowError)?;
+            let quotient = (value / denominator)
+                .checked_mul(numerator)
+                .and_then(|v| v.checked_add(partial / denominator))
+                .ok_or(LoanError::OverflowError)?;
+            (quotient, partial % denominator)
+        }
+    };
+    if rounding == Rounding::Up && remainder != 0 {
+        return quotient.checked_add(1).ok_or(LoanError::OverflowError.into());
+    }
+    Ok(quotient)
+}
+
+// value * numerator / denominator, the result must fit in a u64
+pub fn mul_div(value: u64, numerator: u64, denominator: u64, rounding: Rounding) -> Result<u64> {
+    let result = mul_div_u128(value as u128, numerator as u128, denominator as u128, rounding)?;
+    to_u64(result)
+}
+
+// Smallest integer greater than or equal to value / divisor
+pub fn ceil_div(value: u64, divisor: u64) -> Result<u64> {
+    mul_div(value, 1, divisor, Rounding::Up)
+}
+
+// `bps` basis points of `amount`
+pub fn apply_bps(amount: u64, bps: u64, rounding: Rounding) -> Result<u64> {
+    mul_div(amount, bps, BPS_DENOMINATOR, rounding)
+}
+
+pub fn to_u64(value: u128) -> Result<u64> {
+    Ok(u64::try_from(value).map_err(|_| LoanError::OverflowError)?)
+}
+
+// Interest accrued linearly on `principal` at the annual `rate_bps` over `elapsed` seconds
+pub fn simple_interest(principal: u64, rate_bps: u64, elapsed: i64, rounding: Rounding) -> Result<u64> {
+    let elapsed = u128::try_from(elapsed).map_err(|_| LoanError::OverflowError)?;
+    let numerator = (rate_bps as u128).checked_mul(elapsed).ok_or(LoanError::OverflowError)?;
+    let denominator = BPS_DENOMINATOR as u128 * SECONDS_PER_YEAR as u128;
+    to_u64(mul_div_u128(principal as u128, numerator, denominator, rounding)?)
+}
+
+// Interest on `principal` at the annual `rate_bps` compounded `periods_per_year` times a year
+// over `elapsed` seconds. A partial period accrues simple interest on the compounded balance.
+pub fn compound_interest(
+    principal: u64,
+    rate_bps: u64,
+    elapsed: i64,
+    periods_per_year: u64,
+    rounding: Rounding,
+) -> Result<u64> {
+    let factor = compound_factor(rate_bps, elapsed, periods_per_year, rounding)?;
+    to_u64(mul_div_u128(principal as u128, factor - WAD, WAD, rounding)?)
+}
+
+// Growth factor (1 + r/n)^(elapsed periods) as a WAD
+pub fn compound_factor(rate_bps: u64, elapsed: i64, periods_per_year: u64, rounding: Rounding) -> Result<u128> {
+    require!(periods_per_year > 0, LoanError::OverflowError);
+    let elapsed = u128::try_from(elapsed).map_err(|_| LoanError::OverflowError)?;
+    let seconds_per_year = SECONDS_PER_YEAR as u128;
+    let periods_per_year = periods_per_year as u128;
+
+    // Whole periods elapsed, and the remainder in units of 1 / (n * SECONDS_PER_YEAR) years
+    let scaled_elapsed = elapsed.checked_mul(periods_per_year).ok_or(LoanError::OverflowError)?;
+    let periods = scaled_elapsed / seconds_per_year;
+    let remainder = scaled_elapsed % seconds_per_year;
+
+    let period_denominator = BPS_DENOMINATOR as u128 * periods_per_year;
+    let period_factor = WAD
+        .checked_add(mul_div_u128(WAD, rate_bps as u128, period_denominator, rounding)?)
+        .ok_or(LoanError::OverflowError)?;
+    let factor = wad_pow(period_factor, periods, rounding)?;
+
+    let partial_numerator = (rate_bps as u128).checked_mul(remainder).ok_or(LoanError::OverflowError)?;
+    let partial_denominator = period_denominator
+        .checked_mul(seconds_per_year)
+        .ok_or(LoanError::OverflowError)?;
+    let partial_factor = WAD
+        .checked_add(mul_div_u128(WAD, partial_numerator, partial_denominator, rounding)?)
+        .ok_or(LoanError::OverflowError)?;
+    wad_mul(factor, partial_factor, rounding)
+}
+
+// Product of two WADs
+pub fn wad_mul(a: u128, b: u128, rounding: Rounding) -> Result<u128> {
+    mul_div_u128(a, b, WAD, rounding)
+}
+
+// `base` (a WAD) raised to an integer power by repeated squaring
+pub fn wad_pow(base: u128, exponent: u128, rounding: Rounding) -> Result<u128> {
+    let mut result = WAD;
+    let mut base = base;
+    let mut exponent = exponent;
+    while exponent > 0 {
+        if exponent & 1 == 1 {
+            result = wad_mul(result, base, rounding)?;
+        }
+        exponent >>= 1;
+        if exponent > 0 {
+            base = wad_mul(base, base, rounding)?;
+        }
+    }
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    const DAY: i64 = 24 * 60 * 60;
+
+    #[test]
+    fn mul_div_rounds_in_the_requested_direction() {
+        assert_eq!(mul_div(10, 1, 3, Rounding::Down).unwrap(), 3);
+        assert_eq!(mul_div(10, 1, 3, Rounding::Up).unwrap(), 4);
+        assert_eq!(mul_div(9, 1, 3, Rounding::Down).unwrap(), 3);
+        assert_eq!(mul_div(9, 1, 3, Rounding::Up).unwrap(), 3);
+        assert_eq!(mul_div(0, 7, 3, Rounding::Up).unwrap(), 0);
+    }
+
+    #[test]
+    fn mul_div_uses_wide_intermediates() {
+        assert_eq!(mul_div(u64::MAX, u64::MAX, u64::MAX, Rounding::Down).unwrap(), u64::MAX);
+        assert_eq!(mul_div(u64::MAX, 3, 4, Rounding::Up).unwrap(), 13835058055282163712);
+    }
+
+    #[test]
+    fn mul_div_returns_errors_instead_of_panicking() {
+        assert!(mul_div(1, 1, 0, Rounding::Down).is_err());
+        assert!(mul_div(u64::MAX, 2, 1, Rounding::Down).is_err());
+        assert!(mul_div_u128(u128::MAX, 2, 1, Rounding::Down).is_err());
+        assert!(mul_div_u128(u128::MAX, 1, 1, Rounding::Up).is_ok());
+    }
+
+    #[test]
+    fn mul_div_u128_splits_overflowing_products() {
+        // (1e30 * 1e20) overflows u128 but the quotient fits
+        let value = 1_000_000_000_000_000_000_000_000_000_000u128;
+        assert_eq!(mul_div_u128(value, 100_000_000_000_000_000_000, WAD, Rounding::Down).unwrap(), value * 100);
+        assert_eq!(mul_div_u128(value + 1, 3 * WAD, 2 * WAD, Rounding::Down).unwrap(), value / 2 * 3 + 1);
+        assert_eq!(mul_div_u128(value + 1, 3 * WAD, 2 * WAD, Rounding::Up).unwrap(), value / 2 * 3 + 2);
+    }
+
+    #[test]
+    fn ceil_div_rounds_up() {
+        assert_eq!(ceil_div(10, 5).unwrap(), 2);
+        assert_eq!(ceil_div(11, 5).unwrap(), 3);
+        assert_eq!(ceil_div(0, 5).unwrap(), 0);
+        assert!(ceil_div(1, 0).is_err());
+    }
+
+    #[test]
+    fn apply_bps_matches_percentages() {
+        assert_eq!(apply_bps(1_000_000, 250, Rounding::Down).unwrap(), 25_000);
+        assert_eq!(apply_bps(1, 1, Rounding::Down).unwrap(), 0);
+        assert_eq!(apply_bps(1, 1, Rounding::Up).unwrap(), 1);
+        assert_eq!(apply_bps(u64::MAX, BPS_DENOMINATOR, Rounding::Down).unwrap(), u64::MAX);
+    }
+
+    #[test]
+    fn simple_interest_over_a_year() {
+        // 10% APR on 1_000_000 over a full year
+        assert_eq!(simple_interest(1_000_000, 1000, SECONDS_PER_YEAR, Rounding::Down).unwrap(), 100_000);
+        assert_eq!(simple_interest(1_000_000, 1000, SECONDS_PER_YEAR, Rounding::Up).unwrap(), 100_000);
+        // 30 days: 100_000 * 30 / 365 = 8219.17...
+        assert_eq!(simple_interest(1_000_000, 1000, 30 * DAY, Rounding::Down).unwrap(), 8219);
+        assert_eq!(simple_interest(1_000_000, 1000, 30 * DAY, Rounding::Up).unwrap(), 8220);
+    }
+
+    #[test]
+    fn simple_interest_edge_cases() {
+        assert_eq!(simple_interest(1_000_000, 1000, 0, Rounding::Up).unwrap(), 0);
+        assert_eq!(simple_interest(0, 1000, SECONDS_PER_YEAR, Rounding::Up).unwrap(), 0);
+        assert_eq!(simple_interest(1_000_000, 0, SECONDS_PER_YEAR, Rounding::Up).unwrap(), 0);
+        // One second of interest on a tiny principal still costs the borrower a unit
+        assert_eq!(simple_interest(1, 1, 1, Rounding::Down).unwrap(), 0);
+        assert_eq!(simple_interest(1, 1, 1, Rounding::Up).unwrap(), 1);
+        assert!(simple_interest(1_000_000, 1000, -1, Rounding::Down).is_err());
+        assert!(simple_interest(u64::MAX, u64::MAX, i64::MAX, Rounding::Down).is_err());
+    }
+
+    #[test]
+    fn compound_interest_over_a_year() {
+        // 12% APR compounded monthly: 1_000_000 * (1.01^12 - 1) = 126_825.03...
+        assert_eq!(compound_interest(1_000_000, 1200, SECONDS_PER_YEAR, 12, Rounding::Down).unwrap(), 126_825);
+        assert_eq!(compound_interest(1_000_000, 1200, SECONDS_PER_YEAR, 12, Rounding::Up).unwrap(), 126_826);
+        // Compounding once a year over a year is simple interest
+        assert_eq!(compound_interest(1_000_000, 1000, SECONDS_PER_YEAR, 1, Rounding::Down).unwrap(), 100_000);
+        // 10% compounded yearly over two years: 1_000_000 * (1.1^2 - 1)
+        assert_eq!(compound_interest(1_000_000, 1000, 2 * SECONDS_PER_YEAR, 1, Rounding::Down).unwrap(), 210_000);
+    }
+
+    #[test]
+    fn compound_interest_partial_periods_accrue_linearly() {
+        // Half a year with yearly compounding is half the yearly rate
+        assert_eq!(compound_interest(1_000_000, 1000, SECONDS_PER_YEAR / 2, 1, Rounding::Down).unwrap(), 50_000);
+        // A year and a half: 1.1 * 1.05 - 1
+        let elapsed = SECONDS_PER_YEAR + SECONDS_PER_YEAR / 2;
+        assert_eq!(compound_interest(1_000_000, 1000, elapsed, 1, Rounding::Down).unwrap(), 155_000);
+    }
+
+    #[test]
+    fn compound_interest_edge_cases() {
+        assert_eq!(compound_interest(1_000_000, 1000, 0, 12, Rounding::Up).unwrap(), 0);
+        assert_eq!(compound_interest(1_000_000, 0, SECONDS_PER_YEAR, 12, Rounding::Up).unwrap(), 0);
+        assert!(compound_interest(1_000_000, 1000, SECONDS_PER_YEAR, 0, Rounding::Down).is_err());
+        assert!(compound_interest(1_000_000, 1000, -1, 12, Rounding::Down).is_err());
+        assert!(compound_interest(u64::MAX, 100_000, 100 * SECONDS_PER_YEAR, 365, Rounding::Down).is_err());
+    }
+
+    #[test]
+    fn wad_pow_small_exponents() {
+        let two = 2 * WAD;
+        assert_eq!(wad_pow(two, 0, Rounding::Down).unwrap(), WAD);
+        assert_eq!(wad_pow(two, 1, Rounding::Down).unwrap(), two);
+        assert_eq!(wad_pow(two, 10, Rounding::Down).unwrap(), 1024 * WAD);
+        assert!(wad_pow(two, 128, Rounding::Down).is_err());
+    }
+
+    proptest! {
+        #[test]
+        fn mul_div_up_is_at_most_one_above_down(value: u64, numerator: u64, denominator in 1u64..) {
+            let down = mul_div_u128(value as u128, numerator as u128, denominator as u128, Rounding::Down).unwrap();
+            let up = mul_div_u128(value as u128, numerator as u128, denominator as u128, Rounding::Up).unwrap();
+            prop_assert!(up == down || up == down + 1);
+            // down is the floor: down * denominator <= value * numerator < (down + 1) * denominator
+            let product = value as u128 * numerator as u128;
+            prop_assert!(down * denominator as u128 <= product);
+            prop_assert!(product < (down + 1) * denominator as u128);
+        }
+
+        #[test]
+        fn mul_div_errors_exactly_when_the_result_does_not_fit(value: u64, numerator: u64, denominator in 1u64..) {
+            let expected = value as u128 * numerator as u128 / denominator as u128;
+            let result = mul_div(value, numerator, denominator, Rounding::Down);
+            prop_assert_eq!(result.is_ok(), expected <= u64::MAX as u128);
+        }
+
+        #[test]
+        fn simple_interest_is_monotonic_in_time(
+            principal in 0u64..1_000_000_000_000,
+            rate_bps in 0u64..100_000,
+            elapsed in 0i64..10 * SECONDS_PER_YEAR,
+            extra in 0i64..SECONDS_PER_YEAR,
+        ) {
+            let earlier = simple_interest(principal, rate_bps, elapsed, Rounding::Up).unwrap();
+            let later = simple_interest(principal, rate_bps, elapsed + extra, Rounding::Up).unwrap();
+            prop_assert!(earlier <= later);
+        }
+
+        #[test]
+        fn simple_interest_rounding_favours_the_protocol(
+            principal in 0u64..1_000_000_000_000,
+            rate_bps in 0u64..100_000,
+            elapsed in 0i64..10 * SECONDS_PER_YEAR,
+        ) {
+            let down = simple_interest(principal, rate_bps, elapsed, Rounding::Down).unwrap();
+            let up = simple_interest(principal, rate_bps, elapsed, Rounding::Up).unwrap();
+            prop_assert!(up == down || up == down + 1);
+        }
+
+        #[test]
+        fn split_accruals_never_undercharge(
+            principal in 0u64..1_000_000_000_000,
+            rate_bps in 0u64..100_000,
+            first in 0i64..SECONDS_PER_YEAR,
+            second in 0i64..SECONDS_PER_YEAR,
+        ) {
+            // Accruing in two steps with upward rounding charges at least the one-step amount
+            let once = simple_interest(principal, rate_bps, first + second, Rounding::Up).unwrap();
+            let split = simple_interest(principal, rate_bps, first, Rounding::Up).unwrap()
+                + simple_interest(principal, rate_bps, second, Rounding::Up).unwrap();
+            prop_assert!(split >= once);
+            prop_assert!(split <= once + 1);
+        }
+
+        #[test]
+        fn compound_interest_is_at_least_simple_interest(
+            principal in 0u64..1_000_000_000_000,
+            rate_bps in 0u64..10_000,
+            elapsed in 0i64..5 * SECONDS_PER_YEAR,
+            periods_per_year in 1u64..366,
+        ) {
+            let simple = simple_interest(principal, rate_bps, elapsed, Rounding::Down).unwrap();
+            let compound = compound_interest(principal, rate_bps, elapsed, periods_per_year, Rounding::Down).unwrap();
+            // Allow for the truncation of the per-period rate
+            prop_assert!(compound + 1 + principal / 1_000_000_000 >= simple);
+        }
+
+        #[test]
+        fn compound_rounding_favours_the_protocol(
+            principal in 0u64..1_000_000_000_000,
+            rate_bps in 0u64..10_000,
+            elapsed in 0i64..5 * SECONDS_PER_YEAR,
+            periods_per_year in 1u64..366,
+        ) {
+            let down = compound_interest(principal, rate_bps, elapsed, periods_per_year, Rounding::Down).unwrap();
+            let up = compound_interest(principal, rate_bps, elapsed, periods_per_year, Rounding::Up).unwrap();
+            prop_assert!(up >= down);
+        }
+
+        #[test]
+        fn math_never_panics(principal: u64, rate_bps: u64, elapsed: i64, periods_per_year: u16) {
+            let _ = simple_interest(principal, rate_bps, elapsed, Rounding::Up);
+            let _ = compound_interest(principal, rate_bps, elapsed, periods_per_year as u64, Rounding::Up);
+        }
+    }
+}
@@ -8,6 +8,76 @@ pub enum LoanStatus {
     Defaulted,
     Cancelled,
     Expired,
+    Liquidated,
+    // Defaulted, the collateral is being sold in a descending-price auction
+    InAuction,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RepaymentSchedule {
+    // Principal and interest repaid at the end of the term
+    #[default]
+    Bullet,
+    // Equal principal portions each period, plus the interest accrued on the balance
+    EqualInstallments,
+    // Interest each period, principal repaid in a balloon with the last installment
+    InterestOnly,
+}
+
+// How interest accrues on a loan, `interest_rate` (basis points) is the rate of every model
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Default)]
+pub enum InterestModel {
+    // Annual rate accrued linearly on the outstanding principal
+    #[default]
+    SimpleApr,
+    // Annual rate compounded `periods_per_year` times a year on the unpaid balance
+    Compound { periods_per_year: u64 },
+    // One-off fee on the principal charged when the loan starts, whatever its duration
+    FlatFee,
+}
+
+// How the auction price falls from the start price to the floor
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq)]
+pub enum DecayCurve {
+    // Straight line reaching the floor after `duration` seconds
+    Linear { duration: i64 },
+    // The distance to the floor halves every `half_life` seconds
+    Exponential { half_life: i64 },
+}
+
+// Sale of the collateral of a defaulted loan, prices are relative to the defaulted debt
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq)]
+pub struct AuctionParams {
+    // Opening price above the debt, in basis points
+    pub start_premium_bps: u64,
+    // Lowest price as a share of the debt, in basis points
+    pub floor_bps: u64,
+    pub curve: DecayCurve,
+}
+
+// Emergency switches, each one stops a family of instructions. Repayments, withdrawals of free
+// collateral and the closing of offers have no switch so users can always exit, interest and
+// penalties keep accruing while the protocol is paused. New pledges are stopped by `deposits`
+// and `accept_loans`.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PauseFlags {
+    pub create_offers: bool,
+    pub accept_loans: bool,
+    pub deposits: bool,
+    pub liquidations: bool,
+}
+
+// Balance of a loan at the time of the quote
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct RepaymentQuote {
+    pub loan_id: u64,
+    pub amount_due: u64,
+    pub principal_outstanding: u64,
+    pub interest_due: u64,
+    pub penalty_due: u64,
+    pub installment_due: u64,
+    pub next_due_date: i64,
+    pub quoted_at: i64,
 }
 
 // Événements
@@ -16,11 +86,25 @@ pub struct LoanOfferCreated {
     pub lender: Pubkey,
     pub amount: u64,
     pub interest_rate: u64,
+    pub interest_model: InterestModel,
     pub term: i64,
     pub required_collateral: u64,
     pub loan_offer_id: u64,
 }
 
+#[event]
+pub struct LoanOfferCancelled {
+    pub lender: Pubkey,
+    pub loan_offer_id: u64,
+}
+
+#[event]
+pub struct LoanOfferExpired {
+    pub lender: Pubkey,
+    pub loan_offer_id: u64,
+    pub expired_at: i64,
+}
+
 #[event]
 pub struct LoanActivated {
     pub loan_id: u64,
@@ -33,10 +117,117 @@ pub struct LoanActivated {
 pub struct LoanRepaid {
     pub loan_id: u64,
     pub amount_repaid: u64,
+    pub interest_paid: u64,
+    pub principal_paid: u64,
+    pub penalty_paid: u64,
+    pub remaining_balance: u64,
+    pub next_due_date: i64,
+}
+
+#[event]
+pub struct GuarantorPaid {
+    pub loan_id: u64,
+    pub guarantor: Pubkey,
+    pub amount: u64,
 }
 
 #[event]
 pub struct LoanDefaulted {
     pub loan_id: u64,
     pub defaulted_amount: u64,
-}
\ No newline at end of file
+    pub borrower_collateral_seized: u64,
+    pub guarantor_collateral_seized: u64,
+}
+
+#[event]
+pub struct LoanLiquidated {
+    pub loan_id: u64,
+    pub liquidator: Pubkey,
+    pub debt_repaid: u64,
+    pub borrower_collateral_seized: u64,
+    pub guarantor_collateral_seized: u64,
+    // Health factor before the liquidation, below 10000 means unhealthy
+    pub health_factor_bps: u64,
+    pub remaining_debt: u64,
+    pub closed: bool,
+}
+
+#[event]
+pub struct CollateralAuctionStarted {
+    pub loan_id: u64,
+    pub debt: u64,
+    pub collateral_amount: u64,
+    pub start_price: u64,
+    pub floor_price: u64,
+    pub start_time: i64,
+}
+
+#[event]
+pub struct CollateralAuctionSettled {
+    pub loan_id: u64,
+    pub bidder: Pubkey,
+    pub price: u64,
+    pub lender_proceeds: u64,
+    pub borrower_surplus: u64,
+    pub guarantor_surplus: u64,
+    // Debt the sale did not cover, a loss for the lender
+    pub shortfall: u64,
+}
+
+#[event]
+pub struct ProtocolSettingsUpdated {
+    pub origination_fee_bps: u64,
+    pub interest_fee_bps: u64,
+    pub max_loan_amount: u64,
+    pub max_term: i64,
+    pub max_ltv_bps: u64,
+}
+
+#[event]
+pub struct AdminTransferProposed {
+    pub admin: Pubkey,
+    pub pending_admin: Pubkey,
+}
+
+#[event]
+pub struct AdminTransferred {
+    pub previous_admin: Pubkey,
+    pub new_admin: Pubkey,
+}
+
+#[event]
+pub struct OriginationFeeCollected {
+    pub loan_id: u64,
+    pub mint: Pubkey,
+    pub amount: u64,
+}
+
+#[event]
+pub struct InterestFeeCollected {
+    pub loan_id: u64,
+    pub mint: Pubkey,
+    pub amount: u64,
+}
+
+#[event]
+pub struct TreasuryWithdrawn {
+    pub mint: Pubkey,
+    pub amount: u64,
+    pub destination: Pubkey,
+}
+
+#[event]
+pub struct PauseFlagsUpdated {
+    pub admin: Pubkey,
+    pub flags: PauseFlags,
+}
+
+#[event]
+pub struct MintConfigured {
+    pub mint: Pubkey,
+    pub allow_loan: bool,
+    pub allow_collateral: bool,
+    pub collateral_haircut_bps: u64,
+    pub price_feed: Option<Pubkey>,
+    pub max_total_borrowed: u64,
+}
@@ -0,0 +1,178 @@
+use anchor_lang::prelude::*;
+use crate::errors::LoanError;
+use crate::math::{self, Rounding, BPS_DENOMINATOR};
+use crate::states::MockPriceFeed;
+
+// Values of both sides of a loan are expressed in this common unit, with 12 decimals
+pub const VALUE_DECIMALS: i32 = 12;
+
+// Prices older than this are rejected
+pub const MAX_PRICE_AGE: i64 = 300;
+
+// Price of one whole token, `price * 10^expo` units of the common quote currency
+#[derive(Clone, Copy, Debug)]
+pub struct Price {
+    pub price: u64,
+    pub expo: i32,
+    pub publish_time: i64,
+}
+
+// Any account that can provide a price. Supporting a new oracle means implementing this trait
+// for its account type and dispatching to it in `load_price`.
+pub trait PriceSource {
+    fn get_price(&self) -> Result<Price>;
+}
+
+impl PriceSource for MockPriceFeed {
+    fn get_price(&self) -> Result<Price> {
+        Ok(Price {
+            price: self.price,
+            expo: self.expo,
+            publish_time: self.publish_time,
+        })
+    }
+}
+
+// Read the price from `feed`, which must be the feed recorded on the offer or loan
+pub fn load_price(feed: &AccountInfo, expected: Pubkey, now: i64) -> Result<Price> {
+    require!(feed.key() == expected, LoanError::InvalidPriceFeed);
+
+    let price = if feed.owner == &crate::ID {
+        let data = feed.try_borrow_data()?;
+        MockPriceFeed::try_deserialize(&mut &data[..])?.get_price()?
+    } else {
+        return Err(LoanError::InvalidPriceFeed.into());
+    };
+
+    require!(price.price > 0, LoanError::InvalidPrice);
+    require!(now.saturating_sub(price.publish_time) <= MAX_PRICE_AGE, LoanError::StalePrice);
+    Ok(price)
+}
+
+// 10^(VALUE_DECIMALS + expo - decimals), split into a multiplier and a divisor
+fn value_scale(price: &Price, decimals: u8) -> Result<(u128, u128)> {
+    let exponent = VALUE_DECIMALS + price.expo - decimals as i32;
+    let scale = 10u128
+        .checked_pow(exponent.unsigned_abs())
+        .ok_or(LoanError::OverflowError)?;
+    if exponent >= 0 {
+        Ok((scale, 1))
+    } else {
+        Ok((1, scale))
+    }
+}
+
+// Value of `amount` base units of a token with `decimals` decimals
+pub fn value_of(amount: u64, price: &Price, decimals: u8, rounding: Rounding) -> Result<u128> {
+    let (multiplier, divisor) = value_scale(price, decimals)?;
+    let base = (amount as u128)
+        .checked_mul(price.price as u128)
+        .ok_or(LoanError::OverflowError)?;
+    math::mul_div_u128(base, multiplier, divisor, rounding)
+}
+
+fn units_for_value(value: u128, price: &Price, decimals: u8, rounding: Rounding) -> Result<u128> {
+    let (multiplier, divisor) = value_scale(price, decimals)?;
+    let denominator = (price.price as u128)
+        .checked_mul(multiplier)
+        .ok_or(LoanError::OverflowError)?;
+    math::mul_div_u128(value, divisor, denominator, rounding)
+}
+
+// Base units of a token with `decimals` decimals worth `value`
+pub fn amount_for_value(value: u128, price: &Price, decimals: u8, rounding: Rounding) -> Result<u64> {
+    math::to_u64(units_for_value(value, price, decimals, rounding)?)
+}
+
+// Collateral worth `debt` of the loan token, saturating when no collateral amount is worth as much
+pub fn collateral_for_debt(
+    debt: u64,
+    loan_price: &Price,
+    loan_decimals: u8,
+    collateral_price: &Price,
+    collateral_decimals: u8,
+) -> Result<u64> {
+    let debt_value = value_of(debt, loan_price, loan_decimals, Rounding::Up)?;
+    let collateral = units_for_value(debt_value, collateral_price, collateral_decimals, Rounding::Up)?;
+    Ok(u64::try_from(collateral).unwrap_or(u64::MAX))
+}
+
+// Smallest collateral amount that keeps the loan within `ltv_bps` of the collateral value
+pub fn collateral_for_loan(
+    loan_amount: u64,
+    loan_price: &Price,
+    loan_decimals: u8,
+    collateral_price: &Price,
+    collateral_decimals: u8,
+    ltv_bps: u64,
+) -> Result<u64> {
+    let loan_value = value_of(loan_amount, loan_price, loan_decimals, Rounding::Up)?;
+    let collateral_value = math::mul_div_u128(
+        loan_value,
+        BPS_DENOMINATOR as u128,
+        ltv_bps as u128,
+        Rounding::Up,
+    )?;
+    amount_for_value(collateral_value, collateral_price, collateral_decimals, Rounding::Up)
+}
+
+// Loan value over collateral value in basis points
+pub fn loan_to_value_bps(
+    debt: u64,
+    loan_price: &Price,
+    loan_decimals: u8,
+    collateral: u64,
+    collateral_price: &Price,
+    collateral_decimals: u8,
+) -> Result<u64> {
+    let debt_value = value_of(debt, loan_price, loan_decimals, Rounding::Up)?;
+    let collateral_value = value_of(collateral, collateral_price, collateral_decimals, Rounding::Down)?;
+    if collateral_value == 0 {
+        return Ok(u64::MAX);
+    }
+    let ltv = math::mul_div_u128(debt_value, BPS_DENOMINATOR as u128, collateral_value, Rounding::Up)?;
+    Ok(u64::try_from(ltv).unwrap_or(u64::MAX))
+}
+
+// Liquidation threshold over loan-to-value in basis points, the loan is unhealthy below 10000
+pub fn health_factor_bps(ltv_bps: u64, liquidation_threshold_bps: u64) -> Result<u64> {
+    if ltv_bps == 0 {
+        return Ok(u64::MAX);
+    }
+    math::mul_div(liquidation_threshold_bps, BPS_DENOMINATOR, ltv_bps, Rounding::Down)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn price(price: u64, expo: i32) -> Price {
+        Price { price, expo, publish_time: 0 }
+    }
+
+    #[test]
+    fn collateral_for_debt_converts_between_mints() {
+        // 300 of a 6-decimal token at $1 against a 9-decimal token at $150
+        let usdc = price(100_000_000, -8);
+        let sol = price(15_000_000_000, -8);
+        assert_eq!(collateral_for_debt(300_000_000, &usdc, 6, &sol, 9).unwrap(), 2_000_000_000);
+
+        // And back, 2 of the 9-decimal token are worth 300 of the 6-decimal one
+        assert_eq!(collateral_for_debt(2_000_000_000, &sol, 9, &usdc, 6).unwrap(), 300_000_000);
+    }
+
+    #[test]
+    fn collateral_for_debt_rounds_up() {
+        let usdc = price(100_000_000, -8);
+        let sol = price(15_000_000_000, -8);
+        // One base unit of debt is worth 6.67 collateral units
+        assert_eq!(collateral_for_debt(1, &usdc, 6, &sol, 9).unwrap(), 7);
+    }
+
+    #[test]
+    fn collateral_for_debt_saturates() {
+        let usd = price(100_000_000, -8);
+        let worthless = price(1, -8);
+        assert_eq!(collateral_for_debt(u64::MAX, &usd, 0, &worthless, 9).unwrap(), u64::MAX);
+    }
+}
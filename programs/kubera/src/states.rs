@@ -1,16 +1,40 @@
 use anchor_lang::prelude::*;
-use crate::models::LoanStatus;
+use crate::models::{AuctionParams, DecayCurve, InterestModel, LoanStatus, PauseFlags, RepaymentSchedule};
+use crate::errors::LoanError;
+use crate::math::{self, Rounding};
 
 #[account]
 pub struct LoanOffer {
     pub lender: Pubkey,
     pub amount: u64,
     pub interest_rate: u64,
+    pub interest_model: InterestModel,
     pub term: i64,
     pub status: LoanStatus,
+    // Token lent and repaid
+    pub loan_mint: Pubkey,
+    // Token the borrower pledges as collateral
+    pub collateral_mint: Pubkey,
     pub required_collateral: u64,
     pub loan_offer_id: u64,
     pub guarantor: Option<Pubkey>,
+    pub offer_expires_at: Option<i64>,
+    pub use_escrow: bool,
+    pub min_interest: u64,
+    pub schedule: RepaymentSchedule,
+    pub payment_period: i64,
+    pub grace_period: i64,
+    pub penalty_rate_bps: u64,
+    // Oracle pricing, when both feeds are set the collateral is sized from `ltv_bps`
+    // instead of `required_collateral`
+    pub loan_price_feed: Option<Pubkey>,
+    pub collateral_price_feed: Option<Pubkey>,
+    pub ltv_bps: u64,
+    pub liquidation_threshold_bps: u64,
+    // Extra collateral a liquidator receives on top of the value repaid, in basis points
+    pub liquidation_bonus_bps: u64,
+    // On default the collateral is auctioned for the loan token instead of handed to the lender
+    pub auction: Option<AuctionParams>,
 }
 
 #[account]
@@ -19,12 +43,290 @@ pub struct Loan {
     pub borrower: Pubkey,
     pub amount: u64,
     pub interest_rate: u64,
+    pub interest_model: InterestModel,
     pub term: i64,
     pub start_time: i64,
     pub status: LoanStatus,
+    pub loan_mint: Pubkey,
+    pub collateral_mint: Pubkey,
     pub collateral: u64,
     pub loan_id: u64,
     pub guarantor: Option<Pubkey>,
+    pub borrower_contribution: u64,
+    pub guarantor_contribution: u64,
+    pub loan_offer: Pubkey,
+    pub bump: u8,
+    pub guarantor_interest_rate: u64,
+    pub min_interest: u64,
+    pub principal_outstanding: u64,
+    // Accrued interest not paid yet
+    pub interest_outstanding: u64,
+    pub interest_paid: u64,
+    pub total_repaid: u64,
+    pub last_accrual_time: i64,
+    // Interest accrues from `accrual_start` on the balance at that time, each payment starts a new
+    // accrual so the interest owed does not depend on how often it is accrued in between
+    pub accrual_start: i64,
+    pub interest_since_accrual_start: u64,
+    pub schedule: RepaymentSchedule,
+    // Length of an installment period, the whole term for bullet loans
+    pub payment_period: i64,
+    pub installment_count: u64,
+    pub installments_paid: u64,
+    pub next_due_date: i64,
+    pub missed_installments: u64,
+    // Time after a due date before penalties accrue and the loan can be defaulted
+    pub grace_period: i64,
+    pub penalty_rate_bps: u64,
+    // Accrued late-payment penalty not paid yet
+    pub penalty_outstanding: u64,
+    pub penalty_paid: u64,
+    pub last_penalty_accrual: i64,
+    pub loan_price_feed: Option<Pubkey>,
+    pub collateral_price_feed: Option<Pubkey>,
+    pub ltv_bps: u64,
+    pub liquidation_threshold_bps: u64,
+    // Extra collateral a liquidator receives on top of the value repaid, in basis points
+    pub liquidation_bonus_bps: u64,
+    pub auction: Option<AuctionParams>,
+}
+
+// Actual/365 fixed day-count convention, interest accrues per second over a 365-day year
+pub const SECONDS_PER_YEAR: i64 = 365 * 24 * 60 * 60;
+
+// Upper bound on the compounding frequency, daily
+pub const MAX_PERIODS_PER_YEAR: u64 = 365;
+
+// Upper bound on the number of installments of a loan
+pub const MAX_INSTALLMENTS: u64 = 360;
+
+impl Loan {
+    // Interest charged up front when the loan starts, only flat-fee loans have one
+    pub fn initial_interest(&self) -> Result<u64> {
+        match self.interest_model {
+            InterestModel::FlatFee => math::apply_bps(self.amount, self.interest_rate, Rounding::Up),
+            InterestModel::SimpleApr | InterestModel::Compound { .. } => Ok(0),
+        }
+    }
+
+    // Accrue interest at the annual `interest_rate` (basis points) following the interest model,
+    // up to `now` and never past the end of the term
+    pub fn accrue_interest(&mut self, now: i64) -> Result<()> {
+        let maturity = self.start_time.checked_add(self.term).ok_or(LoanError::OverflowError)?;
+        let accrual_end = now.min(maturity);
+        if accrual_end <= self.last_accrual_time {
+            return Ok(());
+        }
+
+        let elapsed = accrual_end - self.accrual_start;
+        let accrued = match self.interest_model {
+            InterestModel::SimpleApr => {
+                math::simple_interest(self.principal_outstanding, self.interest_rate, elapsed, Rounding::Up)?
+            }
+            InterestModel::Compound { periods_per_year } => {
+                // Compounding periods are counted from the start of the loan, so the result does
+                // not depend on when the accrual started
+                let balance = self.principal_outstanding
+                    .checked_add(self.interest_outstanding)
+                    .ok_or(LoanError::OverflowError)?
+                    .saturating_sub(self.interest_since_accrual_start);
+                let factor_then = math::compound_factor(
+                    self.interest_rate,
+                    self.accrual_start - self.start_time,
+                    periods_per_year,
+                    Rounding::Down,
+                )?;
+                let factor_now = math::compound_factor(
+                    self.interest_rate,
+                    accrual_end - self.start_time,
+                    periods_per_year,
+                    Rounding::Up,
+                )?;
+                let grown = math::mul_div_u128(balance as u128, factor_now, factor_then, Rounding::Up)?;
+                math::to_u64(grown)?.saturating_sub(balance)
+            }
+            // The whole fee was charged when the loan started
+            InterestModel::FlatFee => 0,
+        };
+
+        let interest = accrued.saturating_sub(self.interest_since_accrual_start);
+        self.interest_outstanding = self.interest_outstanding
+            .checked_add(interest)
+            .ok_or(LoanError::OverflowError)?;
+        self.interest_since_accrual_start = accrued;
+        self.last_accrual_time = accrual_end;
+        Ok(())
+    }
+
+    // Accrue the late-payment penalty at the annual `penalty_rate_bps` up to `now`. Each unpaid
+    // installment is charged from the end of its own grace period, the interest owed with the first.
+    pub fn accrue_penalty(&mut self, now: i64) -> Result<()> {
+        let first = self.installments_paid.checked_add(1).ok_or(LoanError::OverflowError)?;
+        let mut penalty: u64 = 0;
+        for k in first..=self.installment_count {
+            let grace_end = self.installment_due_date(k)?
+                .checked_add(self.grace_period)
+                .ok_or(LoanError::OverflowError)?;
+            let accrual_start = self.last_penalty_accrual.max(grace_end);
+            if now <= accrual_start {
+                break;
+            }
+
+            // Principal falling due with installment `k`
+            let principal_before = if k == first {
+                self.principal_outstanding
+            } else {
+                self.principal_outstanding.min(self.scheduled_principal(k - 1)?)
+            };
+            let mut overdue = principal_before.saturating_sub(self.scheduled_principal(k)?);
+            if k == first {
+                overdue = overdue.checked_add(self.interest_owed()).ok_or(LoanError::OverflowError)?;
+            }
+
+            let elapsed = now - accrual_start;
+            let installment_penalty = math::simple_interest(overdue, self.penalty_rate_bps, elapsed, Rounding::Up)?;
+            penalty = penalty.checked_add(installment_penalty).ok_or(LoanError::OverflowError)?;
+        }
+
+        self.penalty_outstanding = self.penalty_outstanding
+            .checked_add(penalty)
+            .ok_or(LoanError::OverflowError)?;
+        self.last_penalty_accrual = self.last_penalty_accrual.max(now);
+        Ok(())
+    }
+
+    // End of the grace period following the next due date
+    pub fn grace_end(&self) -> Result<i64> {
+        let grace_end = self.next_due_date
+            .checked_add(self.grace_period)
+            .ok_or(LoanError::OverflowError)?;
+        Ok(grace_end)
+    }
+
+    // Interest still owed, topped up so the total interest reaches the lender's minimum
+    pub fn interest_owed(&self) -> u64 {
+        self.interest_outstanding.max(self.min_interest.saturating_sub(self.interest_paid))
+    }
+
+    // Amount needed to settle the loan in full
+    pub fn amount_due(&self) -> Result<u64> {
+        let amount_due = self.principal_outstanding
+            .checked_add(self.interest_owed())
+            .and_then(|v| v.checked_add(self.penalty_outstanding))
+            .ok_or(LoanError::OverflowError)?;
+        Ok(amount_due)
+    }
+
+    // Seeds of the loan PDA, which signs for its collateral vault
+    pub fn signer_seeds(&self) -> [&[u8]; 4] {
+        [
+            b"loan",
+            self.loan_offer.as_ref(),
+            self.borrower.as_ref(),
+            std::slice::from_ref(&self.bump),
+        ]
+    }
+
+    // Collateral worth `debt` at the ratio of collateral to principal agreed at origination,
+    // saturating when the debt is worth more than any collateral amount
+    pub fn collateral_at_origination_ratio(&self, debt: u64) -> Result<u64> {
+        let collateral = math::mul_div_u128(debt as u128, self.collateral as u128, self.amount as u128, Rounding::Up)?;
+        Ok(u64::try_from(collateral).unwrap_or(u64::MAX))
+    }
+
+    // Apply a payment to the penalty first, then to the interest and finally to the principal,
+    // returns the (penalty, interest, principal) split
+    pub fn apply_repayment(&mut self, amount: u64) -> Result<(u64, u64, u64)> {
+        let penalty_part = amount.min(self.penalty_outstanding);
+        let interest_part = (amount - penalty_part).min(self.interest_owed());
+        let principal_part = (amount - penalty_part - interest_part).min(self.principal_outstanding);
+
+        self.penalty_outstanding -= penalty_part;
+        self.penalty_paid = self.penalty_paid.checked_add(penalty_part).ok_or(LoanError::OverflowError)?;
+        self.interest_outstanding = self.interest_outstanding.saturating_sub(interest_part);
+        self.interest_paid = self.interest_paid.checked_add(interest_part).ok_or(LoanError::OverflowError)?;
+        self.principal_outstanding -= principal_part;
+        self.total_repaid = self.total_repaid
+            .checked_add(penalty_part + interest_part + principal_part)
+            .ok_or(LoanError::OverflowError)?;
+
+        // Interest now accrues on the new balance
+        self.accrual_start = self.last_accrual_time;
+        self.interest_since_accrual_start = 0;
+        Ok((penalty_part, interest_part, principal_part))
+    }
+
+    // Due date of installment `k` (1-based), the last one falls on the end of the term.
+    // Installment 0 is the start of the loan.
+    pub fn installment_due_date(&self, k: u64) -> Result<i64> {
+        if k >= self.installment_count {
+            return Ok(self.start_time.checked_add(self.term).ok_or(LoanError::OverflowError)?);
+        }
+        let offset = (k as i64).checked_mul(self.payment_period).ok_or(LoanError::OverflowError)?;
+        Ok(self.start_time.checked_add(offset).ok_or(LoanError::OverflowError)?)
+    }
+
+    // Principal that may remain outstanding once installment `k` is paid
+    pub fn scheduled_principal(&self, k: u64) -> Result<u64> {
+        if k >= self.installment_count {
+            return Ok(0);
+        }
+        match self.schedule {
+            RepaymentSchedule::Bullet | RepaymentSchedule::InterestOnly => Ok(self.amount),
+            RepaymentSchedule::EqualInstallments => {
+                // The remaining principal rounds up so no installment falls short of its share
+                let repaid = math::mul_div(self.amount, k, self.installment_count, Rounding::Down)?;
+                Ok(self.amount - repaid)
+            }
+        }
+    }
+
+    // Amount needed to pay the current installment
+    pub fn installment_due(&self) -> Result<u64> {
+        let k = self.installments_paid.checked_add(1).ok_or(LoanError::OverflowError)?;
+        let principal = self.principal_outstanding.saturating_sub(self.scheduled_principal(k)?);
+        let installment_due = principal
+            .checked_add(self.interest_outstanding)
+            .and_then(|v| v.checked_add(self.penalty_outstanding))
+            .ok_or(LoanError::OverflowError)?;
+        Ok(installment_due)
+    }
+
+    // Mark the installments covered by the payments so far as paid, an installment can only be
+    // paid once its period has started. Then move the next due date and count the missed ones.
+    pub fn advance_installments(&mut self, now: i64) -> Result<()> {
+        while self.installments_paid < self.installment_count {
+            let k = self.installments_paid + 1;
+            let period_started = self.installment_due_date(k - 1)? <= now;
+            let settled = self.interest_outstanding == 0
+                && self.penalty_outstanding == 0
+                && self.principal_outstanding <= self.scheduled_principal(k)?;
+            if !period_started || !settled {
+                break;
+            }
+            self.installments_paid = k;
+        }
+
+        let next = self.installments_paid.checked_add(1).ok_or(LoanError::OverflowError)?;
+        self.next_due_date = self.installment_due_date(next)?;
+        self.missed_installments = self.count_missed_installments(now)?;
+        Ok(())
+    }
+
+    // Number of unpaid installments whose due date has passed
+    pub fn count_missed_installments(&self, now: i64) -> Result<u64> {
+        let maturity = self.start_time.checked_add(self.term).ok_or(LoanError::OverflowError)?;
+        let elapsed = now.saturating_sub(self.start_time);
+        let mut passed = if elapsed > 0 {
+            (((elapsed - 1) / self.payment_period) as u64).min(self.installment_count - 1)
+        } else {
+            0
+        };
+        if now > maturity {
+            passed += 1;
+        }
+        Ok(passed.saturating_sub(self.installments_paid))
+    }
 }
 
 #[account]
@@ -43,7 +345,109 @@ pub struct AcceptLoan {
 #[account]
 pub struct UserCollateral {
     pub user: Pubkey,
-    pub amount: u64,
+    // Each collateral mint has its own ledger and pool
+    pub mint: Pubkey,
+    // Collateral the user can withdraw or pledge to a new loan
+    pub free: u64,
+    // Collateral pledged to active loans, held in the loan vaults
+    pub locked: u64,
+    pub active_loans: u64,
+}
+
+impl UserCollateral {
+    // Pledge part of the free balance to a new loan
+    pub fn lock(&mut self, amount: u64) -> Result<()> {
+        self.free = self.free.checked_sub(amount).ok_or(LoanError::InsufficientCollateral)?;
+        self.locked = self.locked.checked_add(amount).ok_or(LoanError::OverflowError)?;
+        self.active_loans = self.active_loans.checked_add(1).ok_or(LoanError::OverflowError)?;
+        Ok(())
+    }
+
+    // Collateral taken out of an active loan by a liquidation, the loan stays open
+    pub fn seize(&mut self, amount: u64) -> Result<()> {
+        self.locked = self.locked.checked_sub(amount).ok_or(LoanError::OverflowError)?;
+        Ok(())
+    }
+
+    // Give back the collateral of a closed loan, `seized` of the `pledged` amount went to the lender
+    pub fn release(&mut self, pledged: u64, seized: u64) -> Result<()> {
+        let returned = pledged.checked_sub(seized).ok_or(LoanError::OverflowError)?;
+        self.locked = self.locked.checked_sub(pledged).ok_or(LoanError::OverflowError)?;
+        self.free = self.free.checked_add(returned).ok_or(LoanError::OverflowError)?;
+        self.active_loans = self.active_loans.checked_sub(1).ok_or(LoanError::OverflowError)?;
+        Ok(())
+    }
+}
+
+// Global settings of the protocol, a singleton only the admin can change
+#[account]
+pub struct ProtocolConfig {
+    pub admin: Pubkey,
+    // Proposed by the admin, takes over once it accepts
+    pub pending_admin: Option<Pubkey>,
+    // Taken from the principal when a loan is accepted, in basis points
+    pub origination_fee_bps: u64,
+    // Taken from the interest paid on every repayment, in basis points
+    pub interest_fee_bps: u64,
+    pub pause: PauseFlags,
+    // Risk limits on new offers, zero means no limit
+    pub max_loan_amount: u64,
+    pub max_term: i64,
+    pub max_ltv_bps: u64,
+    pub bump: u8,
+}
+
+impl ProtocolConfig {
+    // Check an offer against the risk limits
+    pub fn check_limits(&self, amount: u64, term: i64, ltv_bps: u64) -> Result<()> {
+        require!(
+            self.max_loan_amount == 0 || amount <= self.max_loan_amount,
+            LoanError::RiskLimitExceeded
+        );
+        require!(self.max_term == 0 || term <= self.max_term, LoanError::RiskLimitExceeded);
+        require!(self.max_ltv_bps == 0 || ltv_bps <= self.max_ltv_bps, LoanError::RiskLimitExceeded);
+        Ok(())
+    }
+}
+
+// Admin settings of a mint, a mint without one can't be used by the protocol
+#[account]
+pub struct MintConfig {
+    pub mint: Pubkey,
+    pub allow_loan: bool,
+    pub allow_collateral: bool,
+    // Share of the oracle value ignored when the mint backs a loan, in basis points
+    pub collateral_haircut_bps: u64,
+    // The only price feed offers may use for this mint
+    pub price_feed: Option<Pubkey>,
+    // Cap on the principal outstanding across all loans in this mint, zero means no cap
+    pub max_total_borrowed: u64,
+    pub total_borrowed: u64,
+    pub decimals: u8,
+    pub bump: u8,
+}
+
+impl MintConfig {
+    // Count a new loan against the borrow cap
+    pub fn borrow(&mut self, amount: u64) -> Result<()> {
+        let total_borrowed = self.total_borrowed.checked_add(amount).ok_or(LoanError::OverflowError)?;
+        require!(
+            self.max_total_borrowed == 0 || total_borrowed <= self.max_total_borrowed,
+            LoanError::BorrowCapExceeded
+        );
+        self.total_borrowed = total_borrowed;
+        Ok(())
+    }
+
+    // Principal repaid or written off no longer counts against the cap
+    pub fn release_borrowed(&mut self, amount: u64) {
+        self.total_borrowed = self.total_borrowed.saturating_sub(amount);
+    }
+
+    // Apply the haircut to a collateral amount
+    pub fn haircut(&self, amount: u64) -> Result<u64> {
+        math::apply_bps(amount, math::BPS_DENOMINATOR - self.collateral_haircut_bps, Rounding::Down)
+    }
 }
 
 #[account]
@@ -54,6 +458,8 @@ pub struct LoanOfferCounter {
 #[account]
 pub struct GuarantorOffer {
     pub guarantor: Pubkey,
+    // Mint of the collateral the guarantor pledges
+    pub collateral_mint: Pubkey,
     pub amount: u64,
     pub interest_rate: u64,
     pub expiry_date: i64,
@@ -63,4 +469,453 @@ pub struct GuarantorOffer {
 #[account]
 pub struct GuarantorOfferCounter {
     pub count: u64,
-}
\ No newline at end of file
+}
+
+#[account]
+pub struct MockPriceFeed {
+    pub authority: Pubkey,
+    pub mint: Pubkey,
+    pub price: u64,
+    pub expo: i32,
+    pub publish_time: i64,
+}
+
+#[account]
+pub struct CollateralAuction {
+    pub loan: Pubkey,
+    // Pays the rent of the auction and gets it back at settlement
+    pub opened_by: Pubkey,
+    pub debt: u64,
+    pub collateral_amount: u64,
+    pub start_price: u64,
+    pub floor_price: u64,
+    pub curve: DecayCurve,
+    pub start_time: i64,
+    pub bump: u8,
+}
+
+impl CollateralAuction {
+    // Price of the whole lot at `now`, it never goes below the floor. Once the floor is reached
+    // the lot stays on sale at that price, the lender can always bid it themselves.
+    pub fn current_price(&self, now: i64) -> Result<u64> {
+        let elapsed = now.saturating_sub(self.start_time).max(0) as u64;
+        let range = self.start_price.saturating_sub(self.floor_price);
+        let remaining = match self.curve {
+            DecayCurve::Linear { duration } => {
+                let duration = duration as u64;
+                if elapsed >= duration {
+                    0
+                } else {
+                    math::mul_div(range, duration - elapsed, duration, Rounding::Up)?
+                }
+            }
+            DecayCurve::Exponential { half_life } => {
+                let half_life = half_life as u64;
+                let halvings = elapsed / half_life;
+                if halvings >= 64 {
+                    0
+                } else {
+                    // Whole halvings, then a straight line to the next one
+                    let decayed = range >> halvings;
+                    let partial = math::mul_div(decayed, elapsed % half_life, 2 * half_life, Rounding::Down)?;
+                    decayed - partial
+                }
+            }
+        };
+        Ok(self.floor_price.checked_add(remaining).ok_or(LoanError::OverflowError)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DAY: i64 = 24 * 60 * 60;
+
+    // Bullet loan of 1000 at 5% for 30 days, backed by 1400 of collateral
+    fn loan() -> Loan {
+        Loan {
+            lender: Pubkey::new_unique(),
+            borrower: Pubkey::new_unique(),
+            amount: 1_000,
+            interest_rate: 500,
+            interest_model: InterestModel::SimpleApr,
+            term: 30 * DAY,
+            start_time: 0,
+            status: LoanStatus::Active,
+            loan_mint: Pubkey::new_unique(),
+            collateral_mint: Pubkey::new_unique(),
+            collateral: 1_400,
+            loan_id: 0,
+            guarantor: None,
+            borrower_contribution: 1_400,
+            guarantor_contribution: 0,
+            loan_offer: Pubkey::new_unique(),
+            bump: 0,
+            guarantor_interest_rate: 0,
+            min_interest: 0,
+            principal_outstanding: 1_000,
+            interest_outstanding: 0,
+            interest_paid: 0,
+            total_repaid: 0,
+            last_accrual_time: 0,
+            accrual_start: 0,
+            interest_since_accrual_start: 0,
+            schedule: RepaymentSchedule::Bullet,
+            payment_period: 30 * DAY,
+            installment_count: 1,
+            installments_paid: 0,
+            next_due_date: 30 * DAY,
+            missed_installments: 0,
+            grace_period: 0,
+            penalty_rate_bps: 0,
+            penalty_outstanding: 0,
+            penalty_paid: 0,
+            last_penalty_accrual: 0,
+            loan_price_feed: None,
+            collateral_price_feed: None,
+            ltv_bps: 0,
+            liquidation_threshold_bps: 0,
+            liquidation_bonus_bps: 0,
+            auction: None,
+        }
+    }
+
+    // 1200 repaid in 3 monthly installments of 400, with a 5-day grace period
+    fn installment_loan() -> Loan {
+        Loan {
+            amount: 1_200,
+            principal_outstanding: 1_200,
+            term: 90 * DAY,
+            schedule: RepaymentSchedule::EqualInstallments,
+            payment_period: 30 * DAY,
+            installment_count: 3,
+            next_due_date: 30 * DAY,
+            grace_period: 5 * DAY,
+            ..loan()
+        }
+    }
+
+    #[test]
+    fn repayments_go_to_the_penalty_then_interest_then_principal() {
+        let mut loan = Loan {
+            penalty_outstanding: 10,
+            interest_outstanding: 20,
+            ..loan()
+        };
+
+        assert_eq!(loan.apply_repayment(25).unwrap(), (10, 15, 0));
+        assert_eq!(loan.apply_repayment(100).unwrap(), (0, 5, 95));
+
+        assert_eq!(loan.penalty_paid, 10);
+        assert_eq!(loan.interest_paid, 20);
+        assert_eq!(loan.principal_outstanding, 905);
+        assert_eq!(loan.total_repaid, 125);
+        assert_eq!(loan.amount_due().unwrap(), 905);
+    }
+
+    #[test]
+    fn interest_owed_is_topped_up_to_the_minimum() {
+        let mut loan = Loan {
+            min_interest: 30,
+            interest_outstanding: 5,
+            ..loan()
+        };
+        assert_eq!(loan.interest_owed(), 30);
+
+        // The floor counts the interest already paid
+        assert_eq!(loan.apply_repayment(10).unwrap(), (0, 10, 0));
+        assert_eq!(loan.interest_outstanding, 0);
+        assert_eq!(loan.interest_owed(), 20);
+        assert_eq!(loan.amount_due().unwrap(), 1_020);
+
+        // Accrued interest above the floor is owed in full
+        loan.interest_outstanding = 50;
+        assert_eq!(loan.interest_owed(), 50);
+    }
+
+    #[test]
+    fn partial_repayments_settle_the_loan() {
+        let mut loan = loan();
+
+        for day in [10, 20] {
+            loan.accrue_interest(day * DAY).unwrap();
+            loan.apply_repayment(300).unwrap();
+        }
+        loan.accrue_interest(30 * DAY).unwrap();
+        assert!(loan.amount_due().unwrap() > 400);
+
+        let amount_due = loan.amount_due().unwrap();
+        loan.apply_repayment(amount_due).unwrap();
+        assert_eq!(loan.amount_due().unwrap(), 0);
+        assert_eq!(loan.principal_outstanding, 0);
+        assert_eq!(loan.total_repaid, 1_000 + loan.interest_paid);
+    }
+
+    // 1,000,000 at 12% compounded monthly for a year
+    fn compound_loan() -> Loan {
+        Loan {
+            amount: 1_000_000,
+            principal_outstanding: 1_000_000,
+            interest_rate: 1_200,
+            interest_model: InterestModel::Compound { periods_per_year: 12 },
+            term: SECONDS_PER_YEAR,
+            ..loan()
+        }
+    }
+
+    #[test]
+    fn compound_interest_grows_on_the_accrued_interest() {
+        let mut loan = compound_loan();
+        loan.accrue_interest(SECONDS_PER_YEAR).unwrap();
+        // 1.01^12 - 1, rounded up
+        assert_eq!(loan.interest_outstanding, 126_826);
+        assert_eq!(loan.last_accrual_time, SECONDS_PER_YEAR);
+    }
+
+    #[test]
+    fn repeated_compound_accruals_match_a_single_one() {
+        let mut once = compound_loan();
+        once.accrue_interest(200 * DAY).unwrap();
+
+        let mut often = compound_loan();
+        for day in 1..=200 {
+            often.accrue_interest(day * DAY).unwrap();
+        }
+        assert_eq!(often.interest_outstanding, once.interest_outstanding);
+        assert_eq!(often.last_accrual_time, once.last_accrual_time);
+    }
+
+    #[test]
+    fn compound_interest_accrues_on_the_balance_left_after_a_payment() {
+        let mut loan = compound_loan();
+        loan.accrue_interest(100 * DAY).unwrap();
+        let interest = loan.interest_outstanding;
+        loan.apply_repayment(interest + 500_000).unwrap();
+        assert_eq!(loan.principal_outstanding, 500_000);
+
+        let mut once = loan.clone();
+        once.accrue_interest(200 * DAY).unwrap();
+        for day in 101..=200 {
+            loan.accrue_interest(day * DAY).unwrap();
+        }
+        assert_eq!(loan.interest_outstanding, once.interest_outstanding);
+        // Half the balance grows for the remaining 100 days
+        assert!(once.interest_outstanding > 16_000 && once.interest_outstanding < 17_000);
+    }
+
+    #[test]
+    fn a_flat_fee_is_charged_once_up_front() {
+        // Other models charge nothing up front
+        assert_eq!(loan().initial_interest().unwrap(), 0);
+        assert_eq!(compound_loan().initial_interest().unwrap(), 0);
+
+        let mut loan = Loan {
+            interest_model: InterestModel::FlatFee,
+            ..loan()
+        };
+        assert_eq!(loan.initial_interest().unwrap(), 50);
+
+        loan.interest_outstanding = loan.initial_interest().unwrap();
+        loan.accrue_interest(15 * DAY).unwrap();
+        loan.accrue_interest(60 * DAY).unwrap();
+        assert_eq!(loan.interest_outstanding, 50);
+        assert_eq!(loan.amount_due().unwrap(), 1_050);
+    }
+
+    #[test]
+    fn prepaid_installments_count_once_their_period_starts() {
+        let mut loan = installment_loan();
+
+        // Two installments paid on day 10, only the current one is settled yet
+        loan.apply_repayment(800).unwrap();
+        loan.advance_installments(10 * DAY).unwrap();
+        assert_eq!(loan.installments_paid, 1);
+        assert_eq!(loan.next_due_date, 60 * DAY);
+
+        // The second one is settled as soon as its period starts, nothing is missed
+        loan.advance_installments(35 * DAY).unwrap();
+        assert_eq!(loan.installments_paid, 2);
+        assert_eq!(loan.next_due_date, 90 * DAY);
+        assert_eq!(loan.count_missed_installments(65 * DAY).unwrap(), 0);
+        assert_eq!(loan.missed_installments, 0);
+    }
+
+    #[test]
+    fn an_installment_is_missed_after_its_due_date() {
+        let mut loan = installment_loan();
+
+        assert_eq!(loan.count_missed_installments(30 * DAY).unwrap(), 0);
+        assert_eq!(loan.count_missed_installments(30 * DAY + 1).unwrap(), 1);
+        assert_eq!(loan.count_missed_installments(61 * DAY).unwrap(), 2);
+
+        loan.advance_installments(31 * DAY).unwrap();
+        assert_eq!(loan.installments_paid, 0);
+        assert_eq!(loan.missed_installments, 1);
+        assert_eq!(loan.grace_end().unwrap(), 35 * DAY);
+    }
+
+    #[test]
+    fn paying_inside_the_grace_period_recovers_the_loan() {
+        let mut loan = installment_loan();
+        loan.interest_outstanding = 5;
+
+        // The first installment is paid late but before the grace period ends
+        loan.advance_installments(32 * DAY).unwrap();
+        assert_eq!(loan.missed_installments, 1);
+        loan.apply_repayment(405).unwrap();
+        loan.advance_installments(32 * DAY).unwrap();
+
+        assert_eq!(loan.installments_paid, 1);
+        assert_eq!(loan.missed_installments, 0);
+        assert_eq!(loan.count_missed_installments(33 * DAY).unwrap(), 0);
+        assert_eq!(loan.grace_end().unwrap(), 65 * DAY);
+    }
+
+    #[test]
+    fn unpaid_interest_only_installments_are_missed() {
+        let mut loan = Loan {
+            schedule: RepaymentSchedule::InterestOnly,
+            term: 90 * DAY,
+            installment_count: 3,
+            ..loan()
+        };
+
+        // Nothing has been paid, only the interest accrued since the start is owed
+        loan.accrue_interest(61 * DAY).unwrap();
+        assert!(loan.interest_outstanding > 0);
+        loan.advance_installments(61 * DAY).unwrap();
+
+        assert_eq!(loan.installments_paid, 0);
+        assert_eq!(loan.missed_installments, 2);
+        assert_eq!(loan.grace_end().unwrap(), 30 * DAY);
+    }
+
+    // Installment loan charging a 365% penalty, 1% of the overdue balance a day
+    fn penalized_loan() -> Loan {
+        Loan {
+            penalty_rate_bps: 36_500,
+            ..installment_loan()
+        }
+    }
+
+    #[test]
+    fn no_penalty_accrues_inside_the_grace_period() {
+        let mut loan = penalized_loan();
+        loan.accrue_penalty(35 * DAY).unwrap();
+        assert_eq!(loan.penalty_outstanding, 0);
+
+        loan.accrue_penalty(36 * DAY).unwrap();
+        assert_eq!(loan.penalty_outstanding, 4);
+    }
+
+    #[test]
+    fn each_installment_is_penalized_from_its_own_grace_end() {
+        let mut loan = penalized_loan();
+
+        // The first installment is overdue from day 35 and the second from day 65,
+        // the third is not overdue yet
+        loan.accrue_penalty(89 * DAY).unwrap();
+        assert_eq!(loan.penalty_outstanding, 400 * 54 / 100 + 400 * 24 / 100);
+        assert_eq!(loan.last_penalty_accrual, 89 * DAY);
+    }
+
+    #[test]
+    fn repeated_penalty_accruals_match_a_single_one() {
+        let mut once = penalized_loan();
+        once.accrue_penalty(89 * DAY).unwrap();
+
+        let mut often = penalized_loan();
+        for day in [20, 40, 50, 66, 89] {
+            often.accrue_penalty(day * DAY).unwrap();
+        }
+        assert_eq!(often.penalty_outstanding, once.penalty_outstanding);
+    }
+
+    #[test]
+    fn unpaid_interest_is_penalized_with_the_first_overdue_installment() {
+        let mut loan = penalized_loan();
+        loan.interest_outstanding = 100;
+
+        loan.accrue_penalty(45 * DAY).unwrap();
+        assert_eq!(loan.penalty_outstanding, 500 * 10 / 100);
+    }
+
+    #[test]
+    fn paid_installments_are_not_penalized() {
+        let mut loan = penalized_loan();
+        loan.apply_repayment(400).unwrap();
+        loan.advance_installments(10 * DAY).unwrap();
+
+        loan.accrue_penalty(70 * DAY).unwrap();
+        assert_eq!(loan.penalty_outstanding, 400 * 5 / 100);
+    }
+
+    #[test]
+    fn a_bullet_loan_is_missed_after_maturity() {
+        let loan = loan();
+        assert_eq!(loan.count_missed_installments(30 * DAY).unwrap(), 0);
+        assert_eq!(loan.count_missed_installments(30 * DAY + 1).unwrap(), 1);
+        assert_eq!(loan.grace_end().unwrap(), 30 * DAY);
+    }
+
+    // Lot opening at 1500 and falling to a floor of 900 from t=1000
+    fn auction(curve: DecayCurve) -> CollateralAuction {
+        CollateralAuction {
+            loan: Pubkey::new_unique(),
+            opened_by: Pubkey::new_unique(),
+            debt: 1_000,
+            collateral_amount: 1_400,
+            start_price: 1_500,
+            floor_price: 900,
+            curve,
+            start_time: 1_000,
+            bump: 0,
+        }
+    }
+
+    #[test]
+    fn linear_auction_price_decays_to_the_floor() {
+        let auction = auction(DecayCurve::Linear { duration: 100 });
+        assert_eq!(auction.current_price(900).unwrap(), 1_500);
+        assert_eq!(auction.current_price(1_000).unwrap(), 1_500);
+        // Rounds up in favour of the borrower
+        assert_eq!(auction.current_price(1_001).unwrap(), 1_494);
+        assert_eq!(auction.current_price(1_050).unwrap(), 1_200);
+        assert_eq!(auction.current_price(1_100).unwrap(), 900);
+        assert_eq!(auction.current_price(i64::MAX).unwrap(), 900);
+    }
+
+    #[test]
+    fn exponential_auction_price_halves_every_half_life() {
+        let auction = auction(DecayCurve::Exponential { half_life: 100 });
+        assert_eq!(auction.current_price(1_000).unwrap(), 1_500);
+        assert_eq!(auction.current_price(1_100).unwrap(), 1_200);
+        // Halfway to the next halving, a straight line between 300 and 150 above the floor
+        assert_eq!(auction.current_price(1_150).unwrap(), 1_125);
+        assert_eq!(auction.current_price(1_200).unwrap(), 1_050);
+        assert_eq!(auction.current_price(1_000 + 64 * 100).unwrap(), 900);
+        assert_eq!(auction.current_price(i64::MAX).unwrap(), 900);
+    }
+
+    #[test]
+    fn auction_price_never_goes_below_the_floor() {
+        let auction = CollateralAuction {
+            floor_price: 1_600,
+            ..auction(DecayCurve::Linear { duration: 100 })
+        };
+        assert_eq!(auction.current_price(1_000).unwrap(), 1_600);
+        assert_eq!(auction.current_price(1_050).unwrap(), 1_600);
+        assert_eq!(auction.current_price(2_000).unwrap(), 1_600);
+    }
+
+    #[test]
+    fn debt_is_converted_at_the_origination_ratio() {
+        let loan = loan();
+        assert_eq!(loan.collateral_at_origination_ratio(1_000).unwrap(), 1_400);
+        assert_eq!(loan.collateral_at_origination_ratio(500).unwrap(), 700);
+        // Rounds in favour of the lender
+        assert_eq!(loan.collateral_at_origination_ratio(1).unwrap(), 2);
+        assert_eq!(loan.collateral_at_origination_ratio(u64::MAX).unwrap(), u64::MAX);
+    }
+}
@@ -24,6 +24,9 @@ describe("Kubera", () => {
   // Configure the client to use the local cluster.
   anchor.setProvider(anchor.AnchorProvider.env());
 
+  // The upgrade authority of the program, which becomes the protocol admin
+  const admin = (anchor.AnchorProvider.env().wallet as anchor.Wallet).payer;
+
   const connection = new Connection("http://localhost:3000", "confirmed");
   const wallet = new anchor.Wallet(Keypair.generate());
   const provider = new anchor.AnchorProvider(connection, wallet, {
@@ -50,12 +53,15 @@ describe("Kubera", () => {
   let guarantorCollateralPda: PublicKey;
   let loanOfferCounterPda: PublicKey;
   let guarantorOfferCounterPda: PublicKey;
+  let protocolConfigPda: PublicKey;
+  let treasuryVaultPda: PublicKey;
+  let mintConfigPda: PublicKey;
   let collateralTokenAccountPda: PublicKey;
   let guarantorCollateralTokenAccountPda: PublicKey;
   let guarantorOfferPda: PublicKey;
 
   const loanAmount = new anchor.BN(5 * LAMPORTS_PER_SOL);
-  const interestRate = new anchor.BN(500); // 5%
+  const interestRate = new anchor.BN(500); // 5% APR
   const term = new anchor.BN(30 * 24 * 60 * 60); // 30 days in seconds
   const requiredCollateral = new anchor.BN(7 * LAMPORTS_PER_SOL);
   const insufficientCollateral = new anchor.BN(1 * LAMPORTS_PER_SOL);
@@ -134,12 +140,14 @@ describe("Kubera", () => {
     console.log("Initializing borrower collateral account");
     try {
       const [borrowerCollateralTokenAccountPda] = await PublicKey.findProgramAddress(
-        [Buffer.from("collateral_token_account"), borrower.publicKey.toBuffer()],
+        [Buffer.from("collateral_token_account"), borrower.publicKey.toBuffer(), mint.toBuffer()],
         program.programId
       );
   
       const tx = await program.methods.depositCollateral(new anchor.BN(LAMPORTS_PER_SOL / 100)) // Deposit 0.01 token to initialize
         .accounts({
+          protocolConfig: protocolConfigPda,
+          mintConfig: mintConfigPda,
           user: borrower.publicKey,
           userCollateral: borrowerCollateralPda,
           userTokenAccount: borrowerTokenAccount,
@@ -165,6 +173,14 @@ describe("Kubera", () => {
     }
   }
 
+  function findCollateralVaultPda(loan: PublicKey): PublicKey {
+    const [vault] = PublicKey.findProgramAddressSync(
+      [Buffer.from("collateral_vault"), loan.toBuffer()],
+      program.programId
+    );
+    return vault;
+  }
+
   async function checkBalance(publicKey: PublicKey, name: string) {
     const balance = await provider.connection.getBalance(publicKey);
     console.log(`${name} balance: ${balance / LAMPORTS_PER_SOL} SOL`);
@@ -191,17 +207,19 @@ describe("Kubera", () => {
       console.log(`Token balance before initialization: ${balanceBefore.value.uiAmount}`);
   
       const [userCollateralPda] = await PublicKey.findProgramAddress(
-        [Buffer.from("user_collateral"), user.publicKey.toBuffer()],
+        [Buffer.from("user_collateral"), user.publicKey.toBuffer(), mint.toBuffer()],
         program.programId
       );
   
       const [collateralTokenAccountPda] = await PublicKey.findProgramAddress(
-        [Buffer.from("collateral_token_account"), user.publicKey.toBuffer()],
+        [Buffer.from("collateral_token_account"), user.publicKey.toBuffer(), mint.toBuffer()],
         program.programId
       );
   
       const tx = await program.methods.depositCollateral(new anchor.BN(LAMPORTS_PER_SOL / 100)) // Deposit 0.01 token to initialize
         .accounts({
+          protocolConfig: protocolConfigPda,
+          mintConfig: mintConfigPda,
           user: user.publicKey,
           userCollateral: userCollateralPda,
           userTokenAccount: userTokenAccount,
@@ -354,6 +372,80 @@ describe("Kubera", () => {
       await confirmAirdrop(provider.wallet.publicKey, LAMPORTS_PER_SOL);
     }
 
+    // Initialize the protocol and enable the test mint
+    [protocolConfigPda] = PublicKey.findProgramAddressSync(
+      [Buffer.from("protocol_config")],
+      program.programId
+    );
+
+    try {
+      await program.account.protocolConfig.fetch(protocolConfigPda);
+    } catch (e) {
+      console.log("Initializing ProtocolConfig account...");
+      const [programDataPda] = PublicKey.findProgramAddressSync(
+        [program.programId.toBuffer()],
+        new PublicKey("BPFLoaderUpgradeab1e11111111111111111111111")
+      );
+      await program.methods.initializeProtocol({
+        originationFeeBps: new anchor.BN(0),
+        interestFeeBps: new anchor.BN(0),
+        maxLoanAmount: new anchor.BN(0),
+        maxTerm: new anchor.BN(0),
+        maxLtvBps: new anchor.BN(0),
+      })
+        .accounts({
+          admin: admin.publicKey,
+          protocolConfig: protocolConfigPda,
+          program: program.programId,
+          programData: programDataPda,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([admin])
+        .rpc();
+    }
+
+    [mintConfigPda] = PublicKey.findProgramAddressSync(
+      [Buffer.from("mint_config"), mint.toBuffer()],
+      program.programId
+    );
+
+    await program.methods.configureMint({
+      allowLoan: true,
+      allowCollateral: true,
+      collateralHaircutBps: new anchor.BN(0),
+      priceFeed: null,
+      maxTotalBorrowed: new anchor.BN(0),
+    })
+      .accounts({
+        admin: admin.publicKey,
+        protocolConfig: protocolConfigPda,
+        mint: mint,
+        mintConfig: mintConfigPda,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([admin])
+      .rpc();
+
+    [treasuryVaultPda] = PublicKey.findProgramAddressSync(
+      [Buffer.from("treasury"), mint.toBuffer()],
+      program.programId
+    );
+
+    if (!(await provider.connection.getAccountInfo(treasuryVaultPda))) {
+      await program.methods.initializeTreasury()
+        .accounts({
+          admin: admin.publicKey,
+          protocolConfig: protocolConfigPda,
+          mint: mint,
+          treasuryVault: treasuryVaultPda,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+          rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+        })
+        .signers([admin])
+        .rpc();
+    }
+
     // Initialize counters
     try {
       await program.account.loanOfferCounter.fetch(loanOfferCounterPda);
@@ -361,10 +453,12 @@ describe("Kubera", () => {
       console.log("Initializing LoanOfferCounter account...");
       const tx = await program.methods.initializeLoanOfferCounter()
         .accounts({
+          protocolConfig: protocolConfigPda,
           loanOfferCounter: loanOfferCounterPda,
-          payer: provider.wallet.publicKey,
+          admin: admin.publicKey,
           systemProgram: SystemProgram.programId,
         })
+        .signers([admin])
         .rpc();
 
         await provider.connection.confirmTransaction(tx);
@@ -379,10 +473,12 @@ describe("Kubera", () => {
       console.log("Initializing GuarantorOfferCounter account...");
       await program.methods.initializeGuarantorOfferCounter()
         .accounts({
+          protocolConfig: protocolConfigPda,
           guarantorOfferCounter: guarantorOfferCounterPda,
-          payer: provider.wallet.publicKey,
+          admin: admin.publicKey,
           systemProgram: SystemProgram.programId,
         })
+        .signers([admin])
         .rpc();
     }
 
@@ -390,13 +486,13 @@ describe("Kubera", () => {
     console.log("Guarantor Offer Counter PDA:", guarantorOfferCounterPda.toString());
 
     [borrowerCollateralPda] = PublicKey.findProgramAddressSync(
-      [Buffer.from("user_collateral"), borrower.publicKey.toBuffer()],
+      [Buffer.from("user_collateral"), borrower.publicKey.toBuffer(), mint.toBuffer()],
       program.programId
     );
     console.log("Borrower Collateral PDA:", borrowerCollateralPda.toString());
 
     [guarantorCollateralPda] = PublicKey.findProgramAddressSync(
-      [Buffer.from("user_collateral"), guarantor.publicKey.toBuffer()],
+      [Buffer.from("user_collateral"), guarantor.publicKey.toBuffer(), mint.toBuffer()],
       program.programId
     );
     console.log("Guarantor Collateral PDA:", guarantorCollateralPda.toString());
@@ -466,11 +562,31 @@ describe("Kubera", () => {
         interestRate,
         term,
         requiredCollateral,
+        interestModel: { simpleApr: {} },
+        offerExpiresAt: null,
+        useEscrow: false,
+        minInterest: null,
+        schedule: { bullet: {} },
+        paymentPeriod: new anchor.BN(0),
+        gracePeriod: new anchor.BN(0),
+        penaltyRateBps: new anchor.BN(0),
+        loanPriceFeed: null,
+        collateralPriceFeed: null,
+        ltvBps: new anchor.BN(0),
+        liquidationThresholdBps: new anchor.BN(0),
+        liquidationBonusBps: new anchor.BN(0),
+        auction: null,
       })
       .accounts({
+        protocolConfig: protocolConfigPda,
+        loanMintConfig: mintConfigPda,
+        collateralMintConfig: mintConfigPda,
         lender: lender.publicKey,
         loanOfferAccount: loanOfferPda,
         lenderTokenAccount: lenderTokenAccount,
+        loanMint: mint,
+        collateralMint: mint,
+        offerVault: null,
         loanOfferCounter: loanOfferCounterPda,
         tokenProgram: TOKEN_PROGRAM_ID,
         systemProgram: SystemProgram.programId,
@@ -527,9 +643,13 @@ describe("Kubera", () => {
         expiryDate
       )
       .accounts({
+        protocolConfig: protocolConfigPda,
         guarantor: guarantor.publicKey,
+        offerVault: null,
         guarantorOffer: guarantorOfferPda,
         guarantorOfferCounter: guarantorOfferCounterPda,
+        collateralMint: mint,
+        mintConfig: mintConfigPda,
         systemProgram: SystemProgram.programId,
       })
       .signers([guarantor])
@@ -542,6 +662,7 @@ describe("Kubera", () => {
       console.log("GuarantorOffer account:", guarantorOfferAccount);
 
       expect(guarantorOfferAccount.guarantor.toString()).to.equal(guarantor.publicKey.toString());
+      expect(guarantorOfferAccount.collateralMint.toString()).to.equal(mint.toString());
       expect(guarantorOfferAccount.amount.toNumber()).to.equal(guarantorAmount.toNumber());
       expect(guarantorOfferAccount.interestRate.toNumber()).to.equal(guarantorInterestRate.toNumber());
       expect(guarantorOfferAccount.expiryDate.toNumber()).to.equal(expiryDate.toNumber());
@@ -554,6 +675,41 @@ describe("Kubera", () => {
     }
   });
 
+  it("Cannot create a guarantor offer for a mint not accepted as collateral", async () => {
+    const unlistedMint = await createMint(provider.connection, mintAuthority, mintAuthority.publicKey, null, 9);
+    const [unlistedMintConfigPda] = PublicKey.findProgramAddressSync(
+      [Buffer.from("mint_config"), unlistedMint.toBuffer()],
+      program.programId
+    );
+    const offerId = (await program.account.guarantorOfferCounter.fetch(guarantorOfferCounterPda)).count;
+    const [offerPda] = PublicKey.findProgramAddressSync(
+      [Buffer.from("guarantor_offer"), guarantor.publicKey.toBuffer(), offerId.toArrayLike(Buffer, 'le', 8)],
+      program.programId
+    );
+
+    try {
+      await program.methods.createGuarantorOffer(
+        new anchor.BN(10_000_000),
+        new anchor.BN(200),
+        new anchor.BN(Math.floor(Date.now() / 1000) + 60 * 60)
+      )
+        .accounts({
+          protocolConfig: protocolConfigPda,
+          guarantor: guarantor.publicKey,
+          guarantorOffer: offerPda,
+          guarantorOfferCounter: guarantorOfferCounterPda,
+          collateralMint: unlistedMint,
+          mintConfig: unlistedMintConfigPda,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([guarantor])
+        .rpc();
+      expect.fail("A mint without a mint config should be rejected");
+    } catch (error) {
+      expect(error.toString()).to.include("AccountNotInitialized");
+    }
+  });
+
   it("Guarantor can provide necessary collateral", async () => {
     console.log("Depositing collateral by guarantor...");
     const collateralAmount = requiredCollateral.toNumber();
@@ -561,6 +717,8 @@ describe("Kubera", () => {
     try {
       const tx = await program.methods.depositCollateral(new anchor.BN(collateralAmount))
         .accounts({
+          protocolConfig: protocolConfigPda,
+          mintConfig: mintConfigPda,
           user: guarantor.publicKey,
           userCollateral: guarantorCollateralPda,
           userTokenAccount: guarantorTokenAccount,
@@ -577,9 +735,9 @@ describe("Kubera", () => {
       console.log("Collateral deposit transaction confirmed:", tx);
 
       const guarantorCollateralAccount = await program.account.userCollateral.fetch(guarantorCollateralPda);
-      console.log("Collateral amount deposited:", guarantorCollateralAccount.amount.toString());
-      // expect(guarantorCollateralAccount.amount.toNumber()).to.equal(collateralAmount);
-      expect(guarantorCollateralAccount.amount.toNumber()).to.equal(7010000000);
+      console.log("Collateral amount deposited:", guarantorCollateralAccount.free.toString());
+      // expect(guarantorCollateralAccount.free.toNumber()).to.equal(collateralAmount);
+      expect(guarantorCollateralAccount.free.toNumber()).to.equal(7010000000);
 
       console.log("Collateral deposited successfully");
     } catch (error) {
@@ -600,6 +758,8 @@ describe("Kubera", () => {
     try {
       await program.methods.depositCollateral(insufficientCollateral)
         .accounts({
+          protocolConfig: protocolConfigPda,
+          mintConfig: mintConfigPda,
           user: borrower.publicKey,
           userCollateral: borrowerCollateralPda,
           userTokenAccount: borrowerTokenAccount,
@@ -621,8 +781,12 @@ describe("Kubera", () => {
       );
 
       // Attempt to accept the loan
-      await program.methods.acceptLoan(loanOfferId, false)
+      await program.methods.acceptLoan(loanOfferId, false, new anchor.BN(0))
         .accounts({
+          protocolConfig: protocolConfigPda,
+          loanMintConfig: mintConfigPda,
+          collateralMintConfig: mintConfigPda,
+          treasuryVault: treasuryVaultPda,
           borrower: borrower.publicKey,
           lender: lender.publicKey,
           loanOffer: loanOfferPda,
@@ -631,10 +795,18 @@ describe("Kubera", () => {
           borrowerTokenAccount: borrowerTokenAccount,
           lenderTokenAccount: lenderTokenAccount,
           collateralTokenAccount: collateralTokenAccountPda,
-          guarantor: null,
+          loanMint: mint,
+          collateralMint: mint,
+          loanPriceFeed: null,
+          collateralPriceFeed: null,
+          collateralVault: findCollateralVaultPda(loanPda),
+          offerVault: null,
+          guarantorOffer: null,
           guarantorCollateral: null,
+          guarantorCollateralTokenAccount: null,
           tokenProgram: TOKEN_PROGRAM_ID,
           systemProgram: SystemProgram.programId,
+          rent: anchor.web3.SYSVAR_RENT_PUBKEY,
         })
         .signers([borrower])
         .rpc();
@@ -664,6 +836,8 @@ describe("Kubera", () => {
     try {
       await program.methods.depositCollateral(requiredCollateral)
         .accounts({
+          protocolConfig: protocolConfigPda,
+          mintConfig: mintConfigPda,
           user: borrower.publicKey,
           userCollateral: borrowerCollateralPda,
           userTokenAccount: borrowerTokenAccount,
@@ -704,8 +878,12 @@ describe("Kubera", () => {
     console.log("Borrower token account:", borrowerTokenAccount.toString());
     console.log("Loan offer PDA:", loanOfferPda.toString());
     try {
-      await program.methods.acceptLoan(loanOfferId, false)
+      await program.methods.acceptLoan(loanOfferId, false, new anchor.BN(0))
       .accounts({
+        protocolConfig: protocolConfigPda,
+        loanMintConfig: mintConfigPda,
+        collateralMintConfig: mintConfigPda,
+        treasuryVault: treasuryVaultPda,
         borrower: borrower.publicKey,
         lender: lender.publicKey,
         loanOffer: loanOfferPda,
@@ -714,11 +892,19 @@ describe("Kubera", () => {
         borrowerTokenAccount: borrowerTokenAccount,
         lenderTokenAccount: lenderTokenAccount,
         collateralTokenAccount: collateralTokenAccountPda,
+        loanMint: mint,
+        collateralMint: mint,
+        loanPriceFeed: null,
+        collateralPriceFeed: null,
+        collateralVault: findCollateralVaultPda(loanPda),
         loanOfferPda: loanOfferPda,
-        guarantor: null,
+        offerVault: null,
+        guarantorOffer: null,
         guarantorCollateral: null,
+        guarantorCollateralTokenAccount: null,
         tokenProgram: TOKEN_PROGRAM_ID,
         systemProgram: SystemProgram.programId,
+        rent: anchor.web3.SYSVAR_RENT_PUBKEY,
       })
       .signers([borrower])
       .rpc();
@@ -744,6 +930,83 @@ describe("Kubera", () => {
     }
   });
 
+  it("Cannot claim a default before the loan term has elapsed", async () => {
+    console.log("Attempting to claim default on an active loan...");
+
+    try {
+      await program.methods.claimDefault()
+        .accounts({
+          protocolConfig: protocolConfigPda,
+          loanMintConfig: mintConfigPda,
+          collateralMintConfig: mintConfigPda,
+          loanPriceFeed: null,
+          collateralPriceFeed: null,
+          caller: lender.publicKey,
+          loan: loanPda,
+          borrowerCollateral: borrowerCollateralPda,
+          guarantorCollateral: null,
+          collateralTokenAccount: collateralTokenAccountPda,
+          guarantorCollateralTokenAccount: null,
+          borrower: borrower.publicKey,
+          collateralVault: findCollateralVaultPda(loanPda),
+          lenderTokenAccount: lenderTokenAccount,
+          collateralAuction: null,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([lender])
+        .rpc();
+
+      expect.fail("The default should not be claimable before the loan expires");
+    } catch (error) {
+      console.log("Error caught:", error);
+      expect(error.toString()).to.include("LoanNotExpired");
+    }
+  });
+
+  it("Can quote the repayment of an active loan", async () => {
+    const quote = await program.methods.quoteRepayment()
+      .accounts({ loan: loanPda })
+      .view();
+    const loanAccount = await program.account.loan.fetch(loanPda);
+
+    console.log("Repayment quote:", quote);
+    expect(quote.principalOutstanding.toString()).to.equal(loanAccount.principalOutstanding.toString());
+    expect(quote.amountDue.toNumber()).to.be.at.least(loanAccount.principalOutstanding.toNumber());
+    expect(quote.penaltyDue.toNumber()).to.equal(0);
+  });
+
+  it("Cannot liquidate a loan without price feeds", async () => {
+    try {
+      await program.methods.liquidate(new anchor.BN(1))
+        .accounts({
+          protocolConfig: protocolConfigPda,
+          liquidator: lender.publicKey,
+          loan: loanPda,
+          borrowerCollateral: borrowerCollateralPda,
+          guarantorCollateral: null,
+          collateralTokenAccount: collateralTokenAccountPda,
+          guarantorCollateralTokenAccount: null,
+          borrower: borrower.publicKey,
+          collateralVault: findCollateralVaultPda(loanPda),
+          loanMintConfig: mintConfigPda,
+          collateralMintConfig: mintConfigPda,
+          liquidatorTokenAccount: lenderTokenAccount,
+          liquidatorCollateralAccount: lenderTokenAccount,
+          lenderTokenAccount: lenderTokenAccount,
+          loanPriceFeed: lender.publicKey,
+          collateralPriceFeed: lender.publicKey,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([lender])
+        .rpc();
+
+      expect.fail("A loan without price feeds should not be liquidatable");
+    } catch (error) {
+      expect(error.toString()).to.include("InvalidPriceFeed");
+    }
+  });
+
   it("Borrower can accept the loan with a guarantor", async () => {
     console.log("Starting test: Accept loan with guarantor");
     verifySigner(borrower, "Borrower");
@@ -765,11 +1028,31 @@ describe("Kubera", () => {
       interestRate,
       term,
       requiredCollateral,
+      interestModel: { simpleApr: {} },
+      offerExpiresAt: null,
+      useEscrow: false,
+      minInterest: null,
+      schedule: { bullet: {} },
+      paymentPeriod: new anchor.BN(0),
+      gracePeriod: new anchor.BN(0),
+      penaltyRateBps: new anchor.BN(0),
+      loanPriceFeed: null,
+      collateralPriceFeed: null,
+      ltvBps: new anchor.BN(0),
+      liquidationThresholdBps: new anchor.BN(0),
+      liquidationBonusBps: new anchor.BN(0),
+      auction: null,
     })
     .accounts({
+      protocolConfig: protocolConfigPda,
+      loanMintConfig: mintConfigPda,
+      collateralMintConfig: mintConfigPda,
       lender: lender.publicKey,
       loanOfferAccount: newLoanOfferPda,
       lenderTokenAccount: lenderTokenAccount,
+      loanMint: mint,
+      collateralMint: mint,
+      offerVault: null,
       loanOfferCounter: loanOfferCounterPda,
       tokenProgram: TOKEN_PROGRAM_ID,
       systemProgram: SystemProgram.programId,
@@ -780,8 +1063,12 @@ describe("Kubera", () => {
     console.log("New loan offer created for guarantor test");
 
     try {
-      await program.methods.acceptLoan(newLoanOfferId, true)
+      await program.methods.acceptLoan(newLoanOfferId, true, guarantorOfferId)
         .accounts({
+          protocolConfig: protocolConfigPda,
+          loanMintConfig: mintConfigPda,
+          collateralMintConfig: mintConfigPda,
+          treasuryVault: treasuryVaultPda,
           borrower: borrower.publicKey,
           lender: lender.publicKey,
           loanOffer: newLoanOfferPda,
@@ -790,10 +1077,18 @@ describe("Kubera", () => {
           borrowerTokenAccount: borrowerTokenAccount,
           lenderTokenAccount: lenderTokenAccount,
           collateralTokenAccount: collateralTokenAccountPda,
-          guarantor: guarantor.publicKey,
+          loanMint: mint,
+          collateralMint: mint,
+          loanPriceFeed: null,
+          collateralPriceFeed: null,
+          collateralVault: findCollateralVaultPda(newLoanPda),
+          offerVault: null,
+          guarantorOffer: guarantorOfferPda,
           guarantorCollateral: guarantorCollateralPda,
+          guarantorCollateralTokenAccount: guarantorCollateralTokenAccountPda,
           tokenProgram: TOKEN_PROGRAM_ID,
           systemProgram: SystemProgram.programId,
+          rent: anchor.web3.SYSVAR_RENT_PUBKEY,
         })
         .signers([borrower])
         .rpc();
@@ -805,6 +1100,17 @@ describe("Kubera", () => {
       expect(loanAccount.amount.toNumber()).to.equal(loanAmount.toNumber());
       expect(loanAccount.guarantor.toString()).to.equal(guarantor.publicKey.toString());
 
+      // The guarantor's contribution is moved from the free to the locked balance
+      const guarantorCollateralAccount = await program.account.userCollateral.fetch(guarantorCollateralPda);
+      expect(guarantorCollateralAccount.locked.toNumber()).to.equal(loanAccount.guarantorContribution.toNumber());
+      expect(guarantorCollateralAccount.activeLoans.toNumber()).to.equal(1);
+
+      // The contribution is consumed from the guarantor offer's capacity
+      const guarantorOfferAccount = await program.account.guarantorOffer.fetch(guarantorOfferPda);
+      expect(guarantorOfferAccount.amount.toNumber()).to.equal(
+        6 * LAMPORTS_PER_SOL - loanAccount.guarantorContribution.toNumber()
+      );
+
     } catch (error) {
       console.error("Error accepting loan with guarantor:", error);
       throw error;
@@ -817,6 +1123,8 @@ describe("Kubera", () => {
     try {
       const loanAccount = await verifyLoanExists();
 
+      // Interest accrues pro-rata, so a full year of interest covers the amount due
+      // and only the pro-rated amount is charged
       const repaymentAmount = loanAccount.amount.add(
         loanAccount.amount.mul(loanAccount.interestRate).div(new anchor.BN(10000))
       );
@@ -826,10 +1134,19 @@ describe("Kubera", () => {
 
       const tx = await program.methods.repayLoan(repaymentAmount)
         .accounts({
+          protocolConfig: protocolConfigPda,
+          loanMintConfig: mintConfigPda,
+          treasuryVault: treasuryVaultPda,
           borrower: borrower.publicKey,
           loan: loanPda,
+          borrowerCollateral: borrowerCollateralPda,
+          guarantorCollateral: null,
+          collateralTokenAccount: collateralTokenAccountPda,
+          guarantorCollateralTokenAccount: null,
+          collateralVault: findCollateralVaultPda(loanPda),
           borrowerTokenAccount: borrowerTokenAccount,
           lenderTokenAccount: lenderTokenAccount,
+          guarantorTokenAccount: null,
           tokenProgram: TOKEN_PROGRAM_ID,
         })
         .signers([borrower])
@@ -840,6 +1157,15 @@ describe("Kubera", () => {
 
       const updatedLoanAccount = await program.account.loan.fetch(loanPda);
       expect(updatedLoanAccount.status).to.deep.equal({ repaid: {} });
+      expect(updatedLoanAccount.principalOutstanding.toNumber()).to.equal(0);
+      expect(updatedLoanAccount.totalRepaid.toNumber()).to.equal(
+        loanAccount.amount.add(updatedLoanAccount.interestPaid).toNumber()
+      );
+
+      // The borrower's pledged collateral is released back to the free balance
+      const borrowerCollateralAccount = await program.account.userCollateral.fetch(borrowerCollateralPda);
+      console.log("Borrower collateral after repayment:", borrowerCollateralAccount.free.toString());
+      expect(updatedLoanAccount.borrowerContribution.toNumber()).to.equal(requiredCollateral.toNumber());
 
       console.log("Loan repaid successfully");
     } catch (error) {
@@ -854,7 +1180,7 @@ describe("Kubera", () => {
     try {
       // Fetch the current collateral balance
       let guarantorCollateralAccount = await program.account.userCollateral.fetch(guarantorCollateralPda);
-      const collateralAmount = guarantorCollateralAccount.amount;
+      const collateralAmount = guarantorCollateralAccount.free;
       console.log("Current collateral amount:", collateralAmount.toString());
   
       // Withdraw the full collateral amount
@@ -876,10 +1202,10 @@ describe("Kubera", () => {
   
       // Fetch the updated collateral account
       guarantorCollateralAccount = await program.account.userCollateral.fetch(guarantorCollateralPda);
-      console.log("Collateral amount after withdrawal:", guarantorCollateralAccount.amount.toString());
+      console.log("Collateral amount after withdrawal:", guarantorCollateralAccount.free.toString());
   
       // Check if the collateral account is now empty
-      expect(guarantorCollateralAccount.amount.toNumber()).to.equal(0);
+      expect(guarantorCollateralAccount.free.toNumber()).to.equal(0);
   
       // Optionally, check the balance of the guarantor's token account
       const guarantorTokenAccountInfo = await getAccount(provider.connection, guarantorTokenAccount);
@@ -912,11 +1238,31 @@ describe("Kubera", () => {
       interestRate,
       term,
       requiredCollateral,
+      interestModel: { simpleApr: {} },
+      offerExpiresAt: null,
+      useEscrow: false,
+      minInterest: null,
+      schedule: { bullet: {} },
+      paymentPeriod: new anchor.BN(0),
+      gracePeriod: new anchor.BN(0),
+      penaltyRateBps: new anchor.BN(0),
+      loanPriceFeed: null,
+      collateralPriceFeed: null,
+      ltvBps: new anchor.BN(0),
+      liquidationThresholdBps: new anchor.BN(0),
+      liquidationBonusBps: new anchor.BN(0),
+      auction: null,
     })
     .accounts({
+      protocolConfig: protocolConfigPda,
+      loanMintConfig: mintConfigPda,
+      collateralMintConfig: mintConfigPda,
       lender: lender.publicKey,
       loanOfferAccount: newLoanOfferPda,
       lenderTokenAccount: lenderTokenAccount,
+      loanMint: mint,
+      collateralMint: mint,
+      offerVault: null,
       loanOfferCounter: loanOfferCounterPda,
       tokenProgram: TOKEN_PROGRAM_ID,
       systemProgram: SystemProgram.programId,
@@ -930,8 +1276,12 @@ describe("Kubera", () => {
     try {
       const tx = await program.methods.cancelLoanOffer()
         .accounts({
+          protocolConfig: protocolConfigPda,
           lender: lender.publicKey,
           loanOffer: newLoanOfferPda,
+          lenderTokenAccount: lenderTokenAccount,
+          offerVault: null,
+          tokenProgram: TOKEN_PROGRAM_ID,
         })
         .signers([lender])
         .rpc();
@@ -939,8 +1289,13 @@ describe("Kubera", () => {
       await provider.connection.confirmTransaction(tx);
       console.log("Loan offer cancellation transaction confirmed:", tx);
 
-      const cancelledLoanOffer = await program.account.loanOffer.fetch(newLoanOfferPda);
-      expect(cancelledLoanOffer.status).to.deep.equal({ cancelled: {} });
+      // The offer account is closed and its rent returned to the lender
+      const cancelledLoanOffer = await program.account.loanOffer.fetchNullable(newLoanOfferPda);
+      expect(cancelledLoanOffer).to.be.null;
+
+      // The token delegate granted to the offer is revoked
+      const lenderTokenAccountInfo = await getAccount(provider.connection, lenderTokenAccount);
+      expect(lenderTokenAccountInfo.delegate).to.be.null;
 
       console.log("Loan offer cancelled successfully");
     } catch (error) {
@@ -948,4 +1303,1056 @@ describe("Kubera", () => {
       throw error;
     }
   });
-});
\ No newline at end of file
+
+  it("Can create and cancel an escrowed loan offer", async () => {
+    console.log("Creating an escrowed loan offer...");
+
+    const newLoanOfferId = (await program.account.loanOfferCounter.fetch(loanOfferCounterPda)).count;
+    const [newLoanOfferPda] = PublicKey.findProgramAddressSync(
+      [Buffer.from("loan_offer"), lender.publicKey.toBuffer(), newLoanOfferId.toArrayLike(Buffer, 'le', 8)],
+      program.programId
+    );
+    const [offerVaultPda] = PublicKey.findProgramAddressSync(
+      [Buffer.from("offer_vault"), newLoanOfferPda.toBuffer()],
+      program.programId
+    );
+
+    const balanceBefore = await provider.connection.getTokenAccountBalance(lenderTokenAccount);
+
+    await program.methods.createLoanOffer({
+      amount: loanAmount,
+      interestRate,
+      term,
+      requiredCollateral,
+      interestModel: { simpleApr: {} },
+      offerExpiresAt: null,
+      useEscrow: true,
+      minInterest: null,
+      schedule: { bullet: {} },
+      paymentPeriod: new anchor.BN(0),
+      gracePeriod: new anchor.BN(0),
+      penaltyRateBps: new anchor.BN(0),
+      loanPriceFeed: null,
+      collateralPriceFeed: null,
+      ltvBps: new anchor.BN(0),
+      liquidationThresholdBps: new anchor.BN(0),
+      liquidationBonusBps: new anchor.BN(0),
+      auction: null,
+    })
+    .accounts({
+      protocolConfig: protocolConfigPda,
+      loanMintConfig: mintConfigPda,
+      collateralMintConfig: mintConfigPda,
+      lender: lender.publicKey,
+      loanOfferAccount: newLoanOfferPda,
+      lenderTokenAccount: lenderTokenAccount,
+      loanMint: mint,
+      collateralMint: mint,
+      offerVault: offerVaultPda,
+      loanOfferCounter: loanOfferCounterPda,
+      tokenProgram: TOKEN_PROGRAM_ID,
+      systemProgram: SystemProgram.programId,
+    })
+    .signers([lender])
+    .rpc();
+
+    // The offer funds sit in the offer vault
+    const vaultBalance = await provider.connection.getTokenAccountBalance(offerVaultPda);
+    expect(vaultBalance.value.amount).to.equal(loanAmount.toString());
+
+    await program.methods.cancelLoanOffer()
+      .accounts({
+        protocolConfig: protocolConfigPda,
+        lender: lender.publicKey,
+        loanOffer: newLoanOfferPda,
+        lenderTokenAccount: lenderTokenAccount,
+        offerVault: offerVaultPda,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      })
+      .signers([lender])
+      .rpc();
+
+    // Cancelling refunds the lender and closes the vault
+    const balanceAfter = await provider.connection.getTokenAccountBalance(lenderTokenAccount);
+    expect(balanceAfter.value.amount).to.equal(balanceBefore.value.amount);
+    expect(await provider.connection.getAccountInfo(offerVaultPda)).to.be.null;
+  });
+  // Offers expiring a few seconds from now, cranked once they have expired
+  const expiringOffers: { offer: PublicKey, vault: PublicKey }[] = [];
+  let offersExpireAt: number;
+
+  async function expireLoanOffer(caller: Keypair, offer: PublicKey, vault: PublicKey | null) {
+    return program.methods.expireLoanOffer()
+      .accounts({
+        caller: caller.publicKey,
+        protocolConfig: protocolConfigPda,
+        lender: lender.publicKey,
+        loanOffer: offer,
+        lenderTokenAccount: lenderTokenAccount,
+        offerVault: vault,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      })
+      .signers([caller])
+      .rpc();
+  }
+
+  it("Cannot expire a loan offer before its expiry date", async () => {
+    offersExpireAt = (await provider.connection.getBlockTime(await provider.connection.getSlot())) + 4;
+
+    // Two approved offers and an escrowed one
+    for (const useEscrow of [false, false, true]) {
+      const offerId = (await program.account.loanOfferCounter.fetch(loanOfferCounterPda)).count;
+      const [offer] = PublicKey.findProgramAddressSync(
+        [Buffer.from("loan_offer"), lender.publicKey.toBuffer(), offerId.toArrayLike(Buffer, 'le', 8)],
+        program.programId
+      );
+      const [vault] = PublicKey.findProgramAddressSync(
+        [Buffer.from("offer_vault"), offer.toBuffer()],
+        program.programId
+      );
+
+      await program.methods.createLoanOffer({
+        amount: loanAmount,
+        interestRate,
+        term,
+        requiredCollateral,
+        interestModel: { simpleApr: {} },
+        offerExpiresAt: new anchor.BN(offersExpireAt),
+        useEscrow,
+        minInterest: null,
+        schedule: { bullet: {} },
+        paymentPeriod: new anchor.BN(0),
+        gracePeriod: new anchor.BN(0),
+        penaltyRateBps: new anchor.BN(0),
+        loanPriceFeed: null,
+        collateralPriceFeed: null,
+        ltvBps: new anchor.BN(0),
+        liquidationThresholdBps: new anchor.BN(0),
+        liquidationBonusBps: new anchor.BN(0),
+        auction: null,
+      })
+      .accounts({
+        protocolConfig: protocolConfigPda,
+        loanMintConfig: mintConfigPda,
+        collateralMintConfig: mintConfigPda,
+        lender: lender.publicKey,
+        loanOfferAccount: offer,
+        lenderTokenAccount: lenderTokenAccount,
+        loanMint: mint,
+        collateralMint: mint,
+        offerVault: useEscrow ? vault : null,
+        loanOfferCounter: loanOfferCounterPda,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([lender])
+      .rpc();
+
+      expiringOffers.push({ offer, vault });
+    }
+
+    try {
+      await expireLoanOffer(borrower, expiringOffers[0].offer, null);
+      expect.fail("An offer should not expire before its expiry date");
+    } catch (error) {
+      expect(error.toString()).to.include("LoanOfferNotExpired");
+    }
+  });
+
+  it("Anyone can expire a loan offer once it has expired", async () => {
+    while ((await provider.connection.getBlockTime(await provider.connection.getSlot())) <= offersExpireAt) {
+      await sleep(1_000);
+    }
+
+    const { offer } = expiringOffers[0];
+    const lenderLamportsBefore = await provider.connection.getBalance(lender.publicKey);
+    await expireLoanOffer(borrower, offer, null);
+
+    // The rent goes back to the lender, not to the caller
+    expect(await provider.connection.getAccountInfo(offer)).to.be.null;
+    expect(await provider.connection.getBalance(lender.publicKey)).to.be.above(lenderLamportsBefore);
+
+    // Only the owner can revoke, the approval of the latest offer is left in place
+    const lenderAccount = await getAccount(provider.connection, lenderTokenAccount);
+    expect(lenderAccount.delegate.toString()).to.equal(expiringOffers[1].offer.toString());
+  });
+
+  it("Expiring their own offer revokes the lender's approval", async () => {
+    const { offer } = expiringOffers[1];
+    await expireLoanOffer(lender, offer, null);
+
+    expect(await provider.connection.getAccountInfo(offer)).to.be.null;
+    const lenderAccount = await getAccount(provider.connection, lenderTokenAccount);
+    expect(lenderAccount.delegate).to.be.null;
+    expect(Number(lenderAccount.delegatedAmount)).to.equal(0);
+  });
+
+  it("Expiring an escrowed offer refunds the lender", async () => {
+    const { offer, vault } = expiringOffers[2];
+    const balanceBefore = await tokenBalance(lenderTokenAccount);
+    expect(await tokenBalance(vault)).to.equal(loanAmount.toNumber());
+
+    await expireLoanOffer(borrower, offer, vault);
+
+    expect(await tokenBalance(lenderTokenAccount)).to.equal(balanceBefore + loanAmount.toNumber());
+    expect(await provider.connection.getAccountInfo(vault)).to.be.null;
+    expect(await provider.connection.getAccountInfo(offer)).to.be.null;
+  });
+
+  it("Can create and update a mock price feed", async () => {
+    const [priceFeedPda] = PublicKey.findProgramAddressSync(
+      [Buffer.from("mock_price_feed"), admin.publicKey.toBuffer(), mint.toBuffer()],
+      program.programId
+    );
+
+    await program.methods.initializeMockPriceFeed(new anchor.BN(100_000_000), -8)
+      .accounts({
+        protocolConfig: protocolConfigPda,
+        authority: admin.publicKey,
+        mint: mint,
+        priceFeed: priceFeedPda,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([admin])
+      .rpc();
+
+    await program.methods.setMockPrice(new anchor.BN(150_000_000), -8)
+      .accounts({
+        protocolConfig: protocolConfigPda,
+        authority: admin.publicKey,
+        priceFeed: priceFeedPda,
+      })
+      .signers([admin])
+      .rpc();
+
+    const priceFeed = await program.account.mockPriceFeed.fetch(priceFeedPda);
+    expect(priceFeed.price.toString()).to.equal("150000000");
+    expect(priceFeed.expo).to.equal(-8);
+
+    // Only the admin can move the price
+    try {
+      await program.methods.setMockPrice(new anchor.BN(1), -8)
+        .accounts({
+          protocolConfig: protocolConfigPda,
+          authority: borrower.publicKey,
+          priceFeed: priceFeedPda,
+        })
+        .signers([borrower])
+        .rpc();
+      expect.fail("Only the admin should be able to set the price");
+    } catch (error) {
+      expect(error.toString()).to.include("Unauthorized");
+    }
+  });
+
+  it("Cannot create a loan offer with an auction floor above the start price", async () => {
+    const newLoanOfferId = (await program.account.loanOfferCounter.fetch(loanOfferCounterPda)).count;
+    const [newLoanOfferPda] = PublicKey.findProgramAddressSync(
+      [
+        Buffer.from("loan_offer"),
+        lender.publicKey.toBuffer(),
+        newLoanOfferId.toArrayLike(Buffer, 'le', 8)
+      ],
+      program.programId
+    );
+
+    try {
+      await program.methods.createLoanOffer({
+        amount: loanAmount,
+        interestRate,
+        term,
+        requiredCollateral,
+        interestModel: { simpleApr: {} },
+        offerExpiresAt: null,
+        useEscrow: false,
+        minInterest: null,
+        schedule: { bullet: {} },
+        paymentPeriod: new anchor.BN(0),
+        gracePeriod: new anchor.BN(0),
+        penaltyRateBps: new anchor.BN(0),
+        loanPriceFeed: null,
+        collateralPriceFeed: null,
+        ltvBps: new anchor.BN(0),
+        liquidationThresholdBps: new anchor.BN(0),
+        liquidationBonusBps: new anchor.BN(0),
+        auction: {
+          startPremiumBps: new anchor.BN(1_000),
+          floorBps: new anchor.BN(12_000),
+          curve: { linear: { duration: new anchor.BN(3600) } },
+        },
+      })
+      .accounts({
+        protocolConfig: protocolConfigPda,
+        loanMintConfig: mintConfigPda,
+        collateralMintConfig: mintConfigPda,
+        lender: lender.publicKey,
+        loanOfferAccount: newLoanOfferPda,
+        lenderTokenAccount: lenderTokenAccount,
+        loanMint: mint,
+        collateralMint: mint,
+        offerVault: null,
+        loanOfferCounter: loanOfferCounterPda,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([lender])
+      .rpc();
+
+      expect.fail("An auction floor above the start price should be rejected");
+    } catch (error) {
+      expect(error.toString()).to.include("InvalidAuctionParams");
+    }
+  });
+
+  const noPause = {
+    createOffers: false,
+    acceptLoans: false,
+    deposits: false,
+    liquidations: false,
+  };
+
+  it("Only the admin can change the protocol config", async () => {
+    try {
+      await program.methods.setPauseFlags({ ...noPause, createOffers: true })
+        .accounts({
+          admin: borrower.publicKey,
+          protocolConfig: protocolConfigPda,
+        })
+        .signers([borrower])
+        .rpc();
+      expect.fail("Only the admin should be able to pause the protocol");
+    } catch (error) {
+      expect(error.toString()).to.include("Unauthorized");
+    }
+
+    // A proposed admin only takes over once it accepts
+    await program.methods.proposeAdmin(borrower.publicKey)
+      .accounts({
+        admin: admin.publicKey,
+        protocolConfig: protocolConfigPda,
+      })
+      .signers([admin])
+      .rpc();
+
+    let config = await program.account.protocolConfig.fetch(protocolConfigPda);
+    expect(config.admin.toString()).to.equal(admin.publicKey.toString());
+    expect(config.pendingAdmin.toString()).to.equal(borrower.publicKey.toString());
+
+    // Withdraw the proposal by proposing the current admin again
+    await program.methods.proposeAdmin(admin.publicKey)
+      .accounts({
+        admin: admin.publicKey,
+        protocolConfig: protocolConfigPda,
+      })
+      .signers([admin])
+      .rpc();
+
+    try {
+      await program.methods.acceptAdmin()
+        .accounts({
+          newAdmin: borrower.publicKey,
+          protocolConfig: protocolConfigPda,
+        })
+        .signers([borrower])
+        .rpc();
+      expect.fail("Only the pending admin should be able to accept");
+    } catch (error) {
+      expect(error.toString()).to.include("Unauthorized");
+    }
+  });
+
+  it("Only the admin can withdraw from the treasury", async () => {
+    try {
+      await program.methods.withdrawTreasury(new anchor.BN(1))
+        .accounts({
+          admin: borrower.publicKey,
+          protocolConfig: protocolConfigPda,
+          treasuryVault: treasuryVaultPda,
+          destination: borrowerTokenAccount,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([borrower])
+        .rpc();
+      expect.fail("Only the admin should be able to withdraw protocol fees");
+    } catch (error) {
+      expect(error.toString()).to.include("Unauthorized");
+    }
+  });
+
+  it("Cannot deposit collateral while deposits are paused", async () => {
+    await program.methods.setPauseFlags({ ...noPause, deposits: true })
+      .accounts({
+        admin: admin.publicKey,
+        protocolConfig: protocolConfigPda,
+      })
+      .signers([admin])
+      .rpc();
+
+    try {
+      await program.methods.depositCollateral(new anchor.BN(1))
+        .accounts({
+          user: borrower.publicKey,
+          protocolConfig: protocolConfigPda,
+          mintConfig: mintConfigPda,
+          userCollateral: borrowerCollateralPda,
+          userTokenAccount: borrowerTokenAccount,
+          collateralTokenAccount: collateralTokenAccountPda,
+          mint: mint,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+          rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+        })
+        .signers([borrower])
+        .rpc();
+      expect.fail("Deposits should be rejected while paused");
+    } catch (error) {
+      expect(error.toString()).to.include("ProtocolPaused");
+    } finally {
+      await program.methods.setPauseFlags(noPause)
+        .accounts({
+          admin: admin.publicKey,
+          protocolConfig: protocolConfigPda,
+        })
+        .signers([admin])
+        .rpc();
+    }
+  });
+
+  it("Can withdraw free collateral while everything is paused", async () => {
+    await program.methods.setPauseFlags({
+      createOffers: true,
+      acceptLoans: true,
+      deposits: true,
+      liquidations: true,
+    })
+      .accounts({
+        admin: admin.publicKey,
+        protocolConfig: protocolConfigPda,
+      })
+      .signers([admin])
+      .rpc();
+
+    try {
+      // The borrower still has an active loan, only its free balance can leave
+      const before = await program.account.userCollateral.fetch(borrowerCollateralPda);
+      expect(before.activeLoans.toNumber()).to.be.above(0);
+
+      await program.methods.withdrawCollateral(new anchor.BN(1))
+        .accounts({
+          user: borrower.publicKey,
+          userCollateral: borrowerCollateralPda,
+          userTokenAccount: borrowerTokenAccount,
+          collateralTokenAccount: collateralTokenAccountPda,
+          mint: mint,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([borrower])
+        .rpc();
+
+      const after = await program.account.userCollateral.fetch(borrowerCollateralPda);
+      expect(after.free.toNumber()).to.equal(before.free.toNumber() - 1);
+      expect(after.locked.toNumber()).to.equal(before.locked.toNumber());
+    } finally {
+      await program.methods.setPauseFlags(noPause)
+        .accounts({
+          admin: admin.publicKey,
+          protocolConfig: protocolConfigPda,
+        })
+        .signers([admin])
+        .rpc();
+    }
+  });
+
+  it("Only the admin can configure a mint", async () => {
+    try {
+      await program.methods.configureMint({
+        allowLoan: true,
+        allowCollateral: true,
+        collateralHaircutBps: new anchor.BN(0),
+        priceFeed: null,
+        maxTotalBorrowed: new anchor.BN(0),
+      })
+        .accounts({
+          admin: borrower.publicKey,
+          protocolConfig: protocolConfigPda,
+          mint: mint,
+          mintConfig: mintConfigPda,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([borrower])
+        .rpc();
+      expect.fail("Only the admin should be able to configure a mint");
+    } catch (error) {
+      expect(error.toString()).to.include("Unauthorized");
+    }
+
+    const mintConfig = await program.account.mintConfig.fetch(mintConfigPda);
+    expect(mintConfig.allowLoan).to.be.true;
+    expect(mintConfig.decimals).to.equal(9);
+  });
+
+  function sleep(ms: number) {
+    return new Promise((resolve) => setTimeout(resolve, ms));
+  }
+
+  function findCollateralPdas(user: PublicKey, collateralMint: PublicKey): [PublicKey, PublicKey] {
+    const [userCollateral] = PublicKey.findProgramAddressSync(
+      [Buffer.from("user_collateral"), user.toBuffer(), collateralMint.toBuffer()],
+      program.programId
+    );
+    const [collateralPool] = PublicKey.findProgramAddressSync(
+      [Buffer.from("collateral_token_account"), user.toBuffer(), collateralMint.toBuffer()],
+      program.programId
+    );
+    return [userCollateral, collateralPool];
+  }
+
+  async function tokenBalance(tokenAccount: PublicKey): Promise<number> {
+    return Number((await getAccount(provider.connection, tokenAccount)).amount);
+  }
+
+  // Lend `loanAmount` of the test mint to the borrower against `collateral` of another mint
+  async function openLoan(opts: {
+    collateralMint: PublicKey,
+    collateral: anchor.BN,
+    term: anchor.BN,
+    loanPriceFeed?: PublicKey,
+    collateralPriceFeed?: PublicKey,
+    ltvBps?: anchor.BN,
+    liquidationThresholdBps?: anchor.BN,
+    liquidationBonusBps?: anchor.BN,
+    auction?: any,
+    guarantorOfferId?: anchor.BN,
+  }): Promise<PublicKey> {
+    const priced = opts.loanPriceFeed !== undefined;
+    const [collateralMintConfig] = PublicKey.findProgramAddressSync(
+      [Buffer.from("mint_config"), opts.collateralMint.toBuffer()],
+      program.programId
+    );
+    const [userCollateral, collateralPool] = findCollateralPdas(borrower.publicKey, opts.collateralMint);
+    const borrowerCollateralAccount = (await getOrCreateAssociatedTokenAccount(
+      provider.connection, borrower, opts.collateralMint, borrower.publicKey
+    )).address;
+    await mintTo(provider.connection, borrower, opts.collateralMint, borrowerCollateralAccount, mintAuthority, opts.collateral.toNumber());
+
+    await program.methods.depositCollateral(opts.collateral)
+      .accounts({
+        protocolConfig: protocolConfigPda,
+        mintConfig: collateralMintConfig,
+        user: borrower.publicKey,
+        userCollateral: userCollateral,
+        userTokenAccount: borrowerCollateralAccount,
+        collateralTokenAccount: collateralPool,
+        mint: opts.collateralMint,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        systemProgram: SystemProgram.programId,
+        rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+      })
+      .signers([borrower])
+      .rpc();
+
+    const offerId = (await program.account.loanOfferCounter.fetch(loanOfferCounterPda)).count;
+    const [offerPda] = PublicKey.findProgramAddressSync(
+      [Buffer.from("loan_offer"), lender.publicKey.toBuffer(), offerId.toArrayLike(Buffer, 'le', 8)],
+      program.programId
+    );
+    const [newLoanPda] = PublicKey.findProgramAddressSync(
+      [Buffer.from("loan"), offerPda.toBuffer(), borrower.publicKey.toBuffer()],
+      program.programId
+    );
+
+    await program.methods.createLoanOffer({
+      amount: loanAmount,
+      interestRate,
+      term: opts.term,
+      requiredCollateral: priced ? new anchor.BN(0) : opts.collateral,
+      interestModel: { simpleApr: {} },
+      offerExpiresAt: null,
+      useEscrow: false,
+      minInterest: null,
+      schedule: { bullet: {} },
+      paymentPeriod: new anchor.BN(0),
+      gracePeriod: new anchor.BN(0),
+      penaltyRateBps: new anchor.BN(0),
+      loanPriceFeed: opts.loanPriceFeed ?? null,
+      collateralPriceFeed: opts.collateralPriceFeed ?? null,
+      ltvBps: opts.ltvBps ?? new anchor.BN(0),
+      liquidationThresholdBps: opts.liquidationThresholdBps ?? new anchor.BN(0),
+      liquidationBonusBps: opts.liquidationBonusBps ?? new anchor.BN(0),
+      auction: opts.auction ?? null,
+    })
+    .accounts({
+      protocolConfig: protocolConfigPda,
+      loanMintConfig: mintConfigPda,
+      collateralMintConfig: collateralMintConfig,
+      lender: lender.publicKey,
+      loanOfferAccount: offerPda,
+      lenderTokenAccount: lenderTokenAccount,
+      loanMint: mint,
+      collateralMint: opts.collateralMint,
+      offerVault: null,
+      loanOfferCounter: loanOfferCounterPda,
+      tokenProgram: TOKEN_PROGRAM_ID,
+      systemProgram: SystemProgram.programId,
+    })
+    .signers([lender])
+    .rpc();
+
+    const useGuarantor = opts.guarantorOfferId !== undefined;
+    const [guarantorCollateral, guarantorPool] = findCollateralPdas(guarantor.publicKey, opts.collateralMint);
+    const guarantorOffer = useGuarantor
+      ? PublicKey.findProgramAddressSync(
+          [Buffer.from("guarantor_offer"), guarantor.publicKey.toBuffer(), opts.guarantorOfferId.toArrayLike(Buffer, 'le', 8)],
+          program.programId
+        )[0]
+      : null;
+
+    await program.methods.acceptLoan(offerId, useGuarantor, opts.guarantorOfferId ?? new anchor.BN(0))
+      .accounts({
+        protocolConfig: protocolConfigPda,
+        loanMintConfig: mintConfigPda,
+        collateralMintConfig: collateralMintConfig,
+        treasuryVault: treasuryVaultPda,
+        borrower: borrower.publicKey,
+        lender: lender.publicKey,
+        loanOffer: offerPda,
+        loan: newLoanPda,
+        borrowerCollateral: userCollateral,
+        borrowerTokenAccount: borrowerTokenAccount,
+        lenderTokenAccount: lenderTokenAccount,
+        collateralTokenAccount: collateralPool,
+        loanMint: mint,
+        collateralMint: opts.collateralMint,
+        loanPriceFeed: opts.loanPriceFeed ?? null,
+        collateralPriceFeed: opts.collateralPriceFeed ?? null,
+        collateralVault: findCollateralVaultPda(newLoanPda),
+        loanOfferPda: offerPda,
+        offerVault: null,
+        guarantorOffer: guarantorOffer,
+        guarantorCollateral: useGuarantor ? guarantorCollateral : null,
+        guarantorCollateralTokenAccount: useGuarantor ? guarantorPool : null,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        systemProgram: SystemProgram.programId,
+        rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+      })
+      .signers([borrower])
+      .rpc();
+
+    return newLoanPda;
+  }
+
+  // Collateral in a 6-decimal mint, distinct from the 9-decimal loan mint
+  let collateralMint: PublicKey;
+  let collateralMintConfigPda: PublicKey;
+
+  it("Can enable a second mint as collateral", async () => {
+    collateralMint = await createMint(provider.connection, mintAuthority, mintAuthority.publicKey, null, 6);
+    [collateralMintConfigPda] = PublicKey.findProgramAddressSync(
+      [Buffer.from("mint_config"), collateralMint.toBuffer()],
+      program.programId
+    );
+
+    await program.methods.configureMint({
+      allowLoan: false,
+      allowCollateral: true,
+      collateralHaircutBps: new anchor.BN(0),
+      priceFeed: null,
+      maxTotalBorrowed: new anchor.BN(0),
+    })
+      .accounts({
+        admin: admin.publicKey,
+        protocolConfig: protocolConfigPda,
+        mint: collateralMint,
+        mintConfig: collateralMintConfigPda,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([admin])
+      .rpc();
+
+    const mintConfig = await program.account.mintConfig.fetch(collateralMintConfigPda);
+    expect(mintConfig.allowCollateral).to.be.true;
+    expect(mintConfig.decimals).to.equal(6);
+  });
+
+  it("Seizes collateral of another mint at the origination ratio on default", async () => {
+    // 5 tokens lent against 4 collateral tokens, a 0.0008 collateral unit per loan unit
+    const collateral = new anchor.BN(4_000_000);
+    const defaultedLoanPda = await openLoan({ collateralMint, collateral, term: new anchor.BN(3) });
+    const [userCollateral, collateralPool] = findCollateralPdas(borrower.publicKey, collateralMint);
+
+    // Repay half of the principal before the term ends
+    await program.methods.repayLoan(loanAmount.divn(2))
+      .accounts({
+        protocolConfig: protocolConfigPda,
+        loanMintConfig: mintConfigPda,
+        treasuryVault: treasuryVaultPda,
+        borrower: borrower.publicKey,
+        loan: defaultedLoanPda,
+        borrowerCollateral: userCollateral,
+        guarantorCollateral: null,
+        collateralTokenAccount: collateralPool,
+        guarantorCollateralTokenAccount: null,
+        collateralVault: findCollateralVaultPda(defaultedLoanPda),
+        borrowerTokenAccount: borrowerTokenAccount,
+        lenderTokenAccount: lenderTokenAccount,
+        guarantorTokenAccount: null,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      })
+      .signers([borrower])
+      .rpc();
+
+    await sleep(5_000);
+
+    const lenderCollateralAccount = (await getOrCreateAssociatedTokenAccount(
+      provider.connection, lender, collateralMint, lender.publicKey
+    )).address;
+    const seizedBefore = await tokenBalance(lenderCollateralAccount);
+    const poolBefore = await tokenBalance(collateralPool);
+
+    await program.methods.claimDefault()
+      .accounts({
+        protocolConfig: protocolConfigPda,
+        loanMintConfig: mintConfigPda,
+        collateralMintConfig: collateralMintConfigPda,
+        loanPriceFeed: null,
+        collateralPriceFeed: null,
+        caller: lender.publicKey,
+        loan: defaultedLoanPda,
+        borrowerCollateral: userCollateral,
+        guarantorCollateral: null,
+        collateralTokenAccount: collateralPool,
+        guarantorCollateralTokenAccount: null,
+        borrower: borrower.publicKey,
+        collateralVault: findCollateralVaultPda(defaultedLoanPda),
+        lenderTokenAccount: lenderCollateralAccount,
+        collateralAuction: null,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([lender])
+      .rpc();
+
+    const defaultedLoan = await program.account.loan.fetch(defaultedLoanPda);
+    expect(defaultedLoan.status).to.deep.equal({ defaulted: {} });
+
+    // Half the debt is left, so about half the collateral goes to the lender, not all of it
+    const seized = (await tokenBalance(lenderCollateralAccount)) - seizedBefore;
+    const released = (await tokenBalance(collateralPool)) - poolBefore;
+    expect(seized).to.be.at.least(2_000_000);
+    expect(seized).to.be.below(2_000_100);
+    expect(seized + released).to.equal(collateral.toNumber());
+
+    const ledger = await program.account.userCollateral.fetch(userCollateral);
+    expect(ledger.locked.toNumber()).to.equal(0);
+    expect(ledger.free.toNumber()).to.equal(released);
+    expect(await provider.connection.getAccountInfo(findCollateralVaultPda(defaultedLoanPda))).to.be.null;
+  });
+
+  it("Liquidates an unhealthy loan, seizing the borrower's collateral before the guarantor's", async () => {
+    // Both mints priced at $1 by the admin's mock feeds
+    const [loanFeed] = PublicKey.findProgramAddressSync(
+      [Buffer.from("mock_price_feed"), admin.publicKey.toBuffer(), mint.toBuffer()],
+      program.programId
+    );
+    const [collateralFeed] = PublicKey.findProgramAddressSync(
+      [Buffer.from("mock_price_feed"), admin.publicKey.toBuffer(), collateralMint.toBuffer()],
+      program.programId
+    );
+    await program.methods.setMockPrice(new anchor.BN(100_000_000), -8)
+      .accounts({ protocolConfig: protocolConfigPda, authority: admin.publicKey, priceFeed: loanFeed })
+      .signers([admin])
+      .rpc();
+    await program.methods.initializeMockPriceFeed(new anchor.BN(100_000_000), -8)
+      .accounts({
+        protocolConfig: protocolConfigPda,
+        authority: admin.publicKey,
+        mint: collateralMint,
+        priceFeed: collateralFeed,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([admin])
+      .rpc();
+
+    for (const [configuredMint, mintConfig, feed, allowLoan] of [
+      [mint, mintConfigPda, loanFeed, true],
+      [collateralMint, collateralMintConfigPda, collateralFeed, false],
+    ] as [PublicKey, PublicKey, PublicKey, boolean][]) {
+      await program.methods.configureMint({
+        allowLoan,
+        allowCollateral: true,
+        collateralHaircutBps: new anchor.BN(0),
+        priceFeed: feed,
+        maxTotalBorrowed: new anchor.BN(0),
+      })
+        .accounts({
+          admin: admin.publicKey,
+          protocolConfig: protocolConfigPda,
+          mint: configuredMint,
+          mintConfig: mintConfig,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([admin])
+        .rpc();
+    }
+
+    // The guarantor backs the part of the 10 collateral tokens the borrower can't pledge
+    const guarantorCollateralAccount = (await getOrCreateAssociatedTokenAccount(
+      provider.connection, guarantor, collateralMint, guarantor.publicKey
+    )).address;
+    await mintTo(provider.connection, guarantor, collateralMint, guarantorCollateralAccount, mintAuthority, 10_000_000);
+    const [guarantorCollateral, guarantorPool] = findCollateralPdas(guarantor.publicKey, collateralMint);
+    await program.methods.depositCollateral(new anchor.BN(10_000_000))
+      .accounts({
+        protocolConfig: protocolConfigPda,
+        mintConfig: collateralMintConfigPda,
+        user: guarantor.publicKey,
+        userCollateral: guarantorCollateral,
+        userTokenAccount: guarantorCollateralAccount,
+        collateralTokenAccount: guarantorPool,
+        mint: collateralMint,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        systemProgram: SystemProgram.programId,
+        rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+      })
+      .signers([guarantor])
+      .rpc();
+
+    const offerId = (await program.account.guarantorOfferCounter.fetch(guarantorOfferCounterPda)).count;
+    const [offerPda] = PublicKey.findProgramAddressSync(
+      [Buffer.from("guarantor_offer"), guarantor.publicKey.toBuffer(), offerId.toArrayLike(Buffer, 'le', 8)],
+      program.programId
+    );
+    await program.methods.createGuarantorOffer(
+      new anchor.BN(10_000_000),
+      new anchor.BN(200),
+      new anchor.BN(Math.floor(Date.now() / 1000) + 60 * 60)
+    )
+      .accounts({
+        protocolConfig: protocolConfigPda,
+        guarantor: guarantor.publicKey,
+        guarantorOffer: offerPda,
+        guarantorOfferCounter: guarantorOfferCounterPda,
+        collateralMint: collateralMint,
+        mintConfig: collateralMintConfigPda,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([guarantor])
+      .rpc();
+
+    // 5 tokens lent at a 50% loan-to-value, liquidatable above 80%
+    const liquidatedLoanPda = await openLoan({
+      collateralMint,
+      collateral: new anchor.BN(5_000_000),
+      term,
+      loanPriceFeed: loanFeed,
+      collateralPriceFeed: collateralFeed,
+      ltvBps: new anchor.BN(5_000),
+      liquidationThresholdBps: new anchor.BN(8_000),
+      liquidationBonusBps: new anchor.BN(500),
+      guarantorOfferId: offerId,
+    });
+    const before = await program.account.loan.fetch(liquidatedLoanPda);
+    expect(before.borrowerContribution.add(before.guarantorContribution).toNumber()).to.equal(10_000_000);
+    expect(before.guarantorContribution.toNumber()).to.be.above(0);
+
+    const liquidator = Keypair.generate();
+    const airdrop = await provider.connection.requestAirdrop(liquidator.publicKey, 2 * LAMPORTS_PER_SOL);
+    await provider.connection.confirmTransaction(airdrop);
+    const liquidatorTokenAccount = (await getOrCreateAssociatedTokenAccount(
+      provider.connection, liquidator, mint, liquidator.publicKey
+    )).address;
+    const liquidatorCollateralAccount = (await getOrCreateAssociatedTokenAccount(
+      provider.connection, liquidator, collateralMint, liquidator.publicKey
+    )).address;
+    await mintTo(provider.connection, liquidator, mint, liquidatorTokenAccount, mintAuthority, 10 * LAMPORTS_PER_SOL);
+
+    const [borrowerCollateral, borrowerPool] = findCollateralPdas(borrower.publicKey, collateralMint);
+    const liquidate = () => program.methods.liquidate(loanAmount.muln(2))
+      .accounts({
+        protocolConfig: protocolConfigPda,
+        liquidator: liquidator.publicKey,
+        loan: liquidatedLoanPda,
+        borrowerCollateral: borrowerCollateral,
+        guarantorCollateral: guarantorCollateral,
+        collateralTokenAccount: borrowerPool,
+        guarantorCollateralTokenAccount: guarantorPool,
+        borrower: borrower.publicKey,
+        collateralVault: findCollateralVaultPda(liquidatedLoanPda),
+        loanMintConfig: mintConfigPda,
+        collateralMintConfig: collateralMintConfigPda,
+        liquidatorTokenAccount: liquidatorTokenAccount,
+        liquidatorCollateralAccount: liquidatorCollateralAccount,
+        lenderTokenAccount: lenderTokenAccount,
+        loanPriceFeed: loanFeed,
+        collateralPriceFeed: collateralFeed,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      })
+      .signers([liquidator])
+      .rpc();
+
+    try {
+      await liquidate();
+      expect.fail("A healthy loan should not be liquidatable");
+    } catch (error) {
+      expect(error.toString()).to.include("LoanHealthy");
+    }
+
+    // The collateral drops to $0.55, the loan is now above 90% loan-to-value
+    const collateralPrice = 55_000_000;
+    await program.methods.setMockPrice(new anchor.BN(collateralPrice), -8)
+      .accounts({ protocolConfig: protocolConfigPda, authority: admin.publicKey, priceFeed: collateralFeed })
+      .signers([admin])
+      .rpc();
+
+    const lenderBefore = await tokenBalance(lenderTokenAccount);
+    const liquidatorBefore = await tokenBalance(liquidatorTokenAccount);
+    const borrowerPoolBefore = await tokenBalance(borrowerPool);
+    const guarantorPoolBefore = await tokenBalance(guarantorPool);
+
+    await liquidate();
+
+    // The whole debt is repaid to the lender by the liquidator
+    const repaid = (await tokenBalance(lenderTokenAccount)) - lenderBefore;
+    expect(liquidatorBefore - (await tokenBalance(liquidatorTokenAccount))).to.equal(repaid);
+    expect(repaid).to.be.at.least(loanAmount.toNumber());
+
+    // The liquidator gets collateral worth the debt plus the 5% bonus: $1 per 10^9 loan units,
+    // $0.55 per 10^6 collateral units
+    const seized = await tokenBalance(liquidatorCollateralAccount);
+    const seizedValue = new anchor.BN(repaid).muln(1000).muln(10_500).divn(10_000);
+    expect(seized).to.equal(seizedValue.muln(100).div(new anchor.BN(collateralPrice)).toNumber());
+
+    // The borrower's pledge is taken first, the guarantor only loses the rest
+    const after = await program.account.loan.fetch(liquidatedLoanPda);
+    const borrowerSeized = Math.min(seized, before.borrowerContribution.toNumber());
+    const guarantorSeized = seized - borrowerSeized;
+    expect(guarantorSeized).to.be.above(0);
+    expect(after.borrowerContribution.toNumber()).to.equal(before.borrowerContribution.toNumber() - borrowerSeized);
+    expect(after.guarantorContribution.toNumber()).to.equal(before.guarantorContribution.toNumber() - guarantorSeized);
+
+    // The debt is cleared, so the loan is closed and the remaining pledges go back to the pools
+    expect(after.status).to.deep.equal({ liquidated: {} });
+    expect(after.principalOutstanding.toNumber()).to.equal(0);
+    expect((await tokenBalance(borrowerPool)) - borrowerPoolBefore).to.equal(after.borrowerContribution.toNumber());
+    expect((await tokenBalance(guarantorPool)) - guarantorPoolBefore).to.equal(after.guarantorContribution.toNumber());
+    expect(await provider.connection.getAccountInfo(findCollateralVaultPda(liquidatedLoanPda))).to.be.null;
+
+    const borrowerLedger = await program.account.userCollateral.fetch(borrowerCollateral);
+    const guarantorLedger = await program.account.userCollateral.fetch(guarantorCollateral);
+    expect(borrowerLedger.locked.toNumber()).to.equal(0);
+    expect(guarantorLedger.locked.toNumber()).to.equal(0);
+    expect(guarantorLedger.activeLoans.toNumber()).to.equal(0);
+  });
+
+  it("Sells the collateral of a defaulted loan by auction", async () => {
+    // Opens at 110% of the debt and falls to half of it over an hour
+    const collateral = new anchor.BN(8_000_000);
+    const auctionedLoanPda = await openLoan({
+      collateralMint,
+      collateral,
+      term: new anchor.BN(3),
+      auction: {
+        startPremiumBps: new anchor.BN(1_000),
+        floorBps: new anchor.BN(5_000),
+        curve: { linear: { duration: new anchor.BN(3600) } },
+      },
+    });
+    const [borrowerCollateral, borrowerPool] = findCollateralPdas(borrower.publicKey, collateralMint);
+    const [auctionPda] = PublicKey.findProgramAddressSync(
+      [Buffer.from("collateral_auction"), auctionedLoanPda.toBuffer()],
+      program.programId
+    );
+    const lenderCollateralAccount = (await getOrCreateAssociatedTokenAccount(
+      provider.connection, lender, collateralMint, lender.publicKey
+    )).address;
+
+    await sleep(5_000);
+
+    await program.methods.claimDefault()
+      .accounts({
+        protocolConfig: protocolConfigPda,
+        loanMintConfig: mintConfigPda,
+        collateralMintConfig: collateralMintConfigPda,
+        loanPriceFeed: null,
+        collateralPriceFeed: null,
+        caller: lender.publicKey,
+        loan: auctionedLoanPda,
+        borrowerCollateral: borrowerCollateral,
+        guarantorCollateral: null,
+        collateralTokenAccount: borrowerPool,
+        guarantorCollateralTokenAccount: null,
+        borrower: borrower.publicKey,
+        collateralVault: findCollateralVaultPda(auctionedLoanPda),
+        lenderTokenAccount: lenderCollateralAccount,
+        collateralAuction: auctionPda,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([lender])
+      .rpc();
+
+    // The collateral stays in the vault and the pledge stays locked while the auction runs
+    expect((await program.account.loan.fetch(auctionedLoanPda)).status).to.deep.equal({ inAuction: {} });
+    const auction = await program.account.collateralAuction.fetch(auctionPda);
+    expect(auction.collateralAmount.toNumber()).to.equal(collateral.toNumber());
+    expect(auction.startPrice.toString()).to.equal(auction.debt.muln(11_000).addn(9_999).divn(10_000).toString());
+    expect(auction.floorPrice.toString()).to.equal(auction.debt.divn(2).toString());
+    const lockedBefore = (await program.account.userCollateral.fetch(borrowerCollateral)).locked.toNumber();
+    expect(lockedBefore).to.be.at.least(collateral.toNumber());
+
+    const bidder = Keypair.generate();
+    const airdrop = await provider.connection.requestAirdrop(bidder.publicKey, 2 * LAMPORTS_PER_SOL);
+    await provider.connection.confirmTransaction(airdrop);
+    const bidderTokenAccount = (await getOrCreateAssociatedTokenAccount(
+      provider.connection, bidder, mint, bidder.publicKey
+    )).address;
+    const bidderCollateralAccount = (await getOrCreateAssociatedTokenAccount(
+      provider.connection, bidder, collateralMint, bidder.publicKey
+    )).address;
+    await mintTo(provider.connection, bidder, mint, bidderTokenAccount, mintAuthority, 10 * LAMPORTS_PER_SOL);
+
+    const bidderBefore = await tokenBalance(bidderTokenAccount);
+    const lenderBefore = await tokenBalance(lenderTokenAccount);
+    const borrowerBefore = await tokenBalance(borrowerTokenAccount);
+    const freeBefore = (await program.account.userCollateral.fetch(borrowerCollateral)).free.toNumber();
+
+    await program.methods.bidCollateralAuction(auction.startPrice)
+      .accounts({
+        bidder: bidder.publicKey,
+        protocolConfig: protocolConfigPda,
+        loan: auctionedLoanPda,
+        collateralAuction: auctionPda,
+        auctionOpener: lender.publicKey,
+        borrowerCollateral: borrowerCollateral,
+        guarantorCollateral: null,
+        borrower: borrower.publicKey,
+        collateralVault: findCollateralVaultPda(auctionedLoanPda),
+        bidderTokenAccount: bidderTokenAccount,
+        bidderCollateralAccount: bidderCollateralAccount,
+        lenderTokenAccount: lenderTokenAccount,
+        borrowerTokenAccount: borrowerTokenAccount,
+        guarantorTokenAccount: null,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      })
+      .signers([bidder])
+      .rpc();
+
+    // Early in the auction the price is above the debt, the lender is paid in full and the
+    // surplus goes to the borrower, whose collateral was the only one sold
+    const price = bidderBefore - (await tokenBalance(bidderTokenAccount));
+    expect(price).to.be.above(auction.debt.toNumber());
+    expect(price).to.be.at.most(auction.startPrice.toNumber());
+    expect((await tokenBalance(lenderTokenAccount)) - lenderBefore).to.equal(auction.debt.toNumber());
+    expect((await tokenBalance(borrowerTokenAccount)) - borrowerBefore).to.equal(price - auction.debt.toNumber());
+    expect(await tokenBalance(bidderCollateralAccount)).to.equal(collateral.toNumber());
+
+    // The sold pledge is released without returning anything, the vault and the auction are closed
+    const ledger = await program.account.userCollateral.fetch(borrowerCollateral);
+    expect(ledger.locked.toNumber()).to.equal(lockedBefore - collateral.toNumber());
+    expect(ledger.free.toNumber()).to.equal(freeBefore);
+    expect((await program.account.loan.fetch(auctionedLoanPda)).status).to.deep.equal({ defaulted: {} });
+    expect(await provider.connection.getAccountInfo(findCollateralVaultPda(auctionedLoanPda))).to.be.null;
+    expect(await provider.connection.getAccountInfo(auctionPda)).to.be.null;
+  });
+});